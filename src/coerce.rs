@@ -0,0 +1,537 @@
+//! Type coercion between [`Variant`] types, following the rules of `VariantChangeTypeEx`
+
+use crate::com_types::currency::Currency;
+use crate::com_types::date::ComDate;
+use crate::com_types::safearray::SafeArray;
+use crate::convert::VariantConversionError;
+use crate::format::{self, format_float, DisplaySettings};
+use crate::sys::BSTR;
+use crate::{OwnedVariant, Variant, VariantType};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::str::FromStr;
+use windows::core::{ComInterface, IUnknown, Interface, GUID};
+use windows::Win32::System::Com::{IDispatch, DISPATCH_PROPERTYGET, DISPPARAMS};
+use windows::Win32::System::Ole::DISPID_VALUE;
+
+pub use windows::Win32::System::Variant::{
+    VARIANT_ALPHABOOL, VARIANT_LOCALBOOL, VARIANT_NOVALUEPROP, VAR_CHANGE_FLAGS,
+};
+
+/// Intermediate representation of numeric values during coercion
+#[derive(Clone, Copy)]
+pub(crate) enum Num {
+    Int(i128),
+    Float(f64),
+    Dec(Decimal),
+}
+
+impl<'a> Variant<'a> {
+    /// Converts the value to another type, like `VariantChangeTypeEx` with the US English locale.
+    ///
+    /// By-reference values are dereferenced first, so the result never borrows. Numbers are
+    /// rounded to even when converted to integers, and values that don't fit in the target type
+    /// fail with [`VariantConversionError::Overflow`]. `Null` can only be converted to `Null` or
+    /// `Empty`, and arrays and records can't be converted at all.
+    ///
+    /// Supported flags are [`VARIANT_NOVALUEPROP`], which prevents reading the default value of
+    /// `IDispatch` objects, and [`VARIANT_ALPHABOOL`] (or [`VARIANT_LOCALBOOL`]), which converts
+    /// booleans to `"True"` and `"False"` rather than `"-1"` and `"0"`.
+    pub fn change_type(
+        self,
+        target: VariantType,
+        flags: VAR_CHANGE_FLAGS,
+    ) -> Result<Variant<'static>, VariantConversionError> {
+        coerce(dereference(self)?, target, flags)
+    }
+}
+
+/// Copies the value behind a by-reference variant, and detaches by-value variants from `'a`
+pub(crate) fn dereference(value: Variant<'_>) -> Result<Variant<'static>, VariantConversionError> {
+    use Variant::*;
+    Ok(match value {
+        Empty => Empty,
+        Null => Null,
+        Bool(b) => Bool(b),
+        BoolRef(b) => Bool(b.get()),
+        I8(i) => I8(i),
+        I8Ref(i) => I8(*i),
+        I16(i) => I16(i),
+        I16Ref(i) => I16(*i),
+        I32(i) => I32(i),
+        I32Ref(i) => I32(*i),
+        I64(i) => I64(i),
+        I64Ref(i) => I64(*i),
+        U8(i) => U8(i),
+        U8Ref(i) => U8(*i),
+        U16(i) => U16(i),
+        U16Ref(i) => U16(*i),
+        U32(i) => U32(i),
+        U32Ref(i) => U32(*i),
+        U64(i) => U64(i),
+        U64Ref(i) => U64(*i),
+        Int(i) => Int(i),
+        IntRef(i) => Int(*i),
+        UInt(i) => UInt(i),
+        UIntRef(i) => UInt(*i),
+        IntPtr(i) => IntPtr(i),
+        UIntPtr(i) => UIntPtr(i),
+        F32(f) => F32(f),
+        F32Ref(f) => F32(*f),
+        F64(f) => F64(f),
+        F64Ref(f) => F64(*f),
+        Currency(c) => Currency(c),
+        CurrencyRef(c) => Currency((*c).into()),
+        Decimal(d) => Decimal(d),
+        DecimalRef(d) => Decimal((&*d).try_into()?),
+        Date(d) => Date(d),
+        DateRef(d) => Date(NaiveDateTime::try_from(*d)?),
+        String(s) => String(s),
+        StringRef(s) => String(s.clone()),
+        Dispatch(d) => Dispatch(d),
+        DispatchRef(d) => Dispatch(d.clone()),
+        Unknown(u) => Unknown(u),
+        UnknownRef(u) => Unknown(u.clone()),
+        Error(e) => Error(e),
+        ErrorRef(e) => Error(*e),
+        VariantRef(v) => Variant::try_from(&*v.0)?,
+        Array(a) => Array(a),
+        ArrayRef(a) => Array(unsafe { SafeArray::copy_from_raw(*a.ptr.0, a.element_type)? }),
+        Record(r) => Record(r),
+        Raw(v) => Raw(v),
+    })
+}
+
+/// Copies a value, reading through references
+pub(crate) fn copy_value(value: &Variant) -> Result<Variant<'static>, VariantConversionError> {
+    Ok(match value {
+        Variant::BoolRef(b) => Variant::Bool(b.get()),
+        Variant::I8Ref(i) => Variant::I8(**i),
+        Variant::I16Ref(i) => Variant::I16(**i),
+        Variant::I32Ref(i) => Variant::I32(**i),
+        Variant::I64Ref(i) => Variant::I64(**i),
+        Variant::U8Ref(i) => Variant::U8(**i),
+        Variant::U16Ref(i) => Variant::U16(**i),
+        Variant::U32Ref(i) => Variant::U32(**i),
+        Variant::U64Ref(i) => Variant::U64(**i),
+        Variant::IntRef(i) => Variant::Int(**i),
+        Variant::UIntRef(i) => Variant::UInt(**i),
+        Variant::F32Ref(f) => Variant::F32(**f),
+        Variant::F64Ref(f) => Variant::F64(**f),
+        Variant::CurrencyRef(c) => Variant::Currency((**c).into()),
+        Variant::DecimalRef(d) => Variant::Decimal((&**d).try_into()?),
+        Variant::DateRef(d) => Variant::Date(NaiveDateTime::try_from(**d)?),
+        Variant::StringRef(s) => Variant::String((*s).clone()),
+        Variant::DispatchRef(d) => Variant::Dispatch((*d).clone()),
+        Variant::UnknownRef(u) => Variant::Unknown((*u).clone()),
+        Variant::ErrorRef(e) => Variant::Error(**e),
+        Variant::VariantRef(v) => Variant::try_from(&*v.0)?,
+        Variant::ArrayRef(a) => {
+            Variant::Array(unsafe { SafeArray::copy_from_raw(*a.ptr.0, a.element_type)? })
+        }
+        other => other.try_clone()?,
+    })
+}
+
+/// Type of a by-value variant
+pub(crate) fn natural_type(value: &Variant<'static>) -> Option<VariantType> {
+    use VariantType::*;
+    Some(match value {
+        Variant::Empty => VT_EMPTY,
+        Variant::Null => VT_NULL,
+        Variant::Bool(_) => VT_BOOL,
+        Variant::I8(_) => VT_I1,
+        Variant::I16(_) => VT_I2,
+        Variant::I32(_) => VT_I4,
+        Variant::I64(_) => VT_I8,
+        Variant::U8(_) => VT_UI1,
+        Variant::U16(_) => VT_UI2,
+        Variant::U32(_) => VT_UI4,
+        Variant::U64(_) => VT_UI8,
+        Variant::Int(_) => VT_INT,
+        Variant::UInt(_) => VT_UINT,
+        Variant::IntPtr(_) => VT_INT_PTR,
+        Variant::UIntPtr(_) => VT_UINT_PTR,
+        Variant::F32(_) => VT_R4,
+        Variant::F64(_) => VT_R8,
+        Variant::Currency(_) => VT_CY,
+        Variant::Decimal(_) => VT_DECIMAL,
+        Variant::Date(_) => VT_DATE,
+        Variant::String(_) => VT_BSTR,
+        Variant::Dispatch(_) => VT_DISPATCH,
+        Variant::Unknown(_) => VT_UNKNOWN,
+        Variant::Error(_) => VT_ERROR,
+        _ => return None,
+    })
+}
+
+fn coerce(
+    value: Variant<'static>,
+    target: VariantType,
+    flags: VAR_CHANGE_FLAGS,
+) -> Result<Variant<'static>, VariantConversionError> {
+    use VariantType::*;
+    let mismatch = Err(VariantConversionError::TypeMismatch(target));
+    if natural_type(&value) == Some(target) {
+        return Ok(value);
+    }
+
+    match (value, target) {
+        (_, VT_EMPTY) => Ok(Variant::Empty),
+        (Variant::Empty, VT_NULL) => Ok(Variant::Null),
+        (Variant::Null, _) | (_, VT_NULL) => mismatch,
+
+        (Variant::Empty, VT_DISPATCH) => Ok(Variant::Dispatch(None)),
+        (Variant::Unknown(u), VT_DISPATCH) => match u {
+            Some(u) => query::<IDispatch>(&u).map_or(mismatch, |d| Ok(Variant::Dispatch(Some(d)))),
+            None => Ok(Variant::Dispatch(None)),
+        },
+        (Variant::Empty, VT_UNKNOWN) => Ok(Variant::Unknown(None)),
+        (Variant::Dispatch(d), VT_UNKNOWN) => match d {
+            Some(d) => query::<IUnknown>(&d).map_or(mismatch, |u| Ok(Variant::Unknown(Some(u)))),
+            None => Ok(Variant::Unknown(None)),
+        },
+        (_, VT_DISPATCH | VT_UNKNOWN) => mismatch,
+        (Variant::Dispatch(Some(d)), _) if (flags & VARIANT_NOVALUEPROP).0 == 0 => {
+            coerce(value_property(&d)?, target, flags)
+        }
+        (Variant::Dispatch(_) | Variant::Unknown(_), _) => mismatch,
+
+        (Variant::Error(_), _) | (_, VT_ERROR) => mismatch,
+        (Variant::Array(_) | Variant::Record(_), _) => mismatch,
+
+        (value, VT_BSTR) => Ok(Variant::String(BSTR::from(format_value(value, flags)?))),
+
+        // VB converts True to all ones, which wraps for the smaller unsigned types
+        (Variant::Bool(b), VT_UI1) => Ok(Variant::U8(if b { u8::MAX } else { 0 })),
+        (Variant::Bool(b), VT_UI2) => Ok(Variant::U16(if b { u16::MAX } else { 0 })),
+        (Variant::Bool(b), VT_UI4) => Ok(Variant::U32(if b { u32::MAX } else { 0 })),
+        (Variant::Bool(b), VT_UINT) => Ok(Variant::UInt(if b { u32::MAX } else { 0 })),
+
+        (Variant::String(s), VT_BOOL) => {
+            let s = s.to_string();
+            let s = s.trim();
+            if s.eq_ignore_ascii_case("true") {
+                Ok(Variant::Bool(true))
+            } else if s.eq_ignore_ascii_case("false") {
+                Ok(Variant::Bool(false))
+            } else {
+                parse_number(s).map_or(mismatch, |n| to_type(n, VT_BOOL))
+            }
+        }
+        (Variant::String(s), VT_DATE) => match parse_date(&s.to_string()) {
+            Some(date) => to_type(Num::Float(ComDate::try_from(date)?.0), VT_DATE),
+            None => parse_number(&s.to_string()).map_or(mismatch, |n| to_type(n, VT_DATE)),
+        },
+
+        (value, _) => to_type(to_number(value, target)?, target),
+    }
+}
+
+/// Calls `QueryInterface`. COM calls in this module go through the vtable rather than the
+/// wrappers of the `windows` crate, whose errors need oleaut32, so that they also work with the
+/// `portable` feature.
+fn query<T: ComInterface>(obj: &impl ComInterface) -> Option<T> {
+    let mut result = None;
+    let _ = unsafe { obj.query(&T::IID, &mut result as *mut Option<T> as *mut _) };
+    result
+}
+
+/// Reads the default property of an object, as used when converting it to a simple type
+pub(crate) fn value_property(obj: &IDispatch) -> Result<Variant<'static>, VariantConversionError> {
+    let mut result = OwnedVariant::new();
+    let hr = unsafe {
+        (obj.vtable().Invoke)(
+            obj.as_raw(),
+            DISPID_VALUE as i32,
+            &GUID::zeroed(),
+            0,
+            DISPATCH_PROPERTYGET,
+            &DISPPARAMS::default(),
+            result.as_mut_ptr() as *mut _,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if hr.is_err() {
+        return Err(VariantConversionError::ComError(hr));
+    }
+    result.try_into()
+}
+
+pub(crate) fn to_number(
+    value: Variant<'static>,
+    target: VariantType,
+) -> Result<Num, VariantConversionError> {
+    Ok(match value {
+        Variant::Empty => Num::Int(0),
+        Variant::Bool(b) => Num::Int(if b { -1 } else { 0 }),
+        Variant::I8(i) => Num::Int(i.into()),
+        Variant::I16(i) => Num::Int(i.into()),
+        Variant::I32(i) => Num::Int(i.into()),
+        Variant::I64(i) => Num::Int(i.into()),
+        Variant::U8(i) => Num::Int(i.into()),
+        Variant::U16(i) => Num::Int(i.into()),
+        Variant::U32(i) => Num::Int(i.into()),
+        Variant::U64(i) => Num::Int(i.into()),
+        Variant::Int(i) => Num::Int(i.into()),
+        Variant::UInt(i) => Num::Int(i.into()),
+        Variant::IntPtr(i) => Num::Int(i as i128),
+        Variant::UIntPtr(i) => Num::Int(i as i128),
+        Variant::F32(f) => Num::Float(f.into()),
+        Variant::F64(f) => Num::Float(f),
+        Variant::Currency(c) => Num::Dec(c.0),
+        Variant::Decimal(d) => Num::Dec(d),
+        Variant::Date(d) => Num::Float(ComDate::try_from(d)?.0),
+        Variant::String(s) => {
+            parse_number(&s.to_string()).ok_or(VariantConversionError::TypeMismatch(target))?
+        }
+        _ => return Err(VariantConversionError::TypeMismatch(target)),
+    })
+}
+
+pub(crate) fn to_type(
+    value: Num,
+    target: VariantType,
+) -> Result<Variant<'static>, VariantConversionError> {
+    use VariantType::*;
+    let overflow = |_| VariantConversionError::Overflow(target);
+    Ok(match target {
+        VT_BOOL => Variant::Bool(match value {
+            Num::Int(i) => i != 0,
+            Num::Float(f) => f != 0.0,
+            Num::Dec(d) => !d.is_zero(),
+        }),
+        VT_I1 => Variant::I8(to_int(value, target)?.try_into().map_err(overflow)?),
+        VT_I2 => Variant::I16(to_int(value, target)?.try_into().map_err(overflow)?),
+        VT_I4 => Variant::I32(to_int(value, target)?.try_into().map_err(overflow)?),
+        VT_I8 => Variant::I64(to_int(value, target)?.try_into().map_err(overflow)?),
+        VT_UI1 => Variant::U8(to_int(value, target)?.try_into().map_err(overflow)?),
+        VT_UI2 => Variant::U16(to_int(value, target)?.try_into().map_err(overflow)?),
+        VT_UI4 => Variant::U32(to_int(value, target)?.try_into().map_err(overflow)?),
+        VT_UI8 => Variant::U64(to_int(value, target)?.try_into().map_err(overflow)?),
+        VT_INT => Variant::Int(to_int(value, target)?.try_into().map_err(overflow)?),
+        VT_UINT => Variant::UInt(to_int(value, target)?.try_into().map_err(overflow)?),
+        VT_INT_PTR => Variant::IntPtr(to_int(value, target)?.try_into().map_err(overflow)?),
+        VT_UINT_PTR => Variant::UIntPtr(to_int(value, target)?.try_into().map_err(overflow)?),
+        VT_R4 => {
+            let f = to_float(value);
+            if f.is_finite() && f.abs() > f32::MAX as f64 {
+                return Err(VariantConversionError::Overflow(target));
+            }
+            Variant::F32(f as f32)
+        }
+        VT_R8 => Variant::F64(to_float(value)),
+        VT_CY => Variant::Currency(Currency::from_decimal(
+            to_decimal(value, target)?,
+            RoundingStrategy::MidpointNearestEven,
+        )?),
+        VT_DECIMAL => Variant::Decimal(to_decimal(value, target)?),
+        VT_DATE => Variant::Date(NaiveDateTime::try_from(ComDate(to_float(value)))?),
+        _ => return Err(VariantConversionError::TypeMismatch(target)),
+    })
+}
+
+pub(crate) fn to_int(value: Num, target: VariantType) -> Result<i128, VariantConversionError> {
+    let overflow = VariantConversionError::Overflow(target);
+    match value {
+        Num::Int(i) => Ok(i),
+        Num::Float(f) => {
+            let f = f.round_ties_even();
+            if f.is_finite() && f.abs() < 1e38 {
+                Ok(f as i128)
+            } else {
+                Err(overflow)
+            }
+        }
+        Num::Dec(d) => d
+            .round_dp_with_strategy(0, RoundingStrategy::MidpointNearestEven)
+            .to_i128()
+            .ok_or(overflow),
+    }
+}
+
+pub(crate) fn to_float(value: Num) -> f64 {
+    match value {
+        Num::Int(i) => i as f64,
+        Num::Float(f) => f,
+        Num::Dec(d) => d.to_f64().unwrap_or(f64::NAN),
+    }
+}
+
+pub(crate) fn to_decimal(
+    value: Num,
+    target: VariantType,
+) -> Result<Decimal, VariantConversionError> {
+    let overflow = VariantConversionError::Overflow(target);
+    match value {
+        Num::Int(i) => Decimal::try_from_i128_with_scale(i, 0).map_err(|_| overflow),
+        Num::Float(f) => Decimal::from_f64(f).ok_or(overflow),
+        Num::Dec(d) => Ok(d),
+    }
+}
+
+/// Parses a number the way VB does: surrounding spaces, a currency sign, thousands separators,
+/// parentheses for negative numbers, exponents and `&H`/`&O` prefixes are accepted.
+fn parse_number(s: &str) -> Option<Num> {
+    let s = s.trim();
+    for (prefix, radix) in [("&h", 16), ("&o", 8)] {
+        if s.len() > 2 && s.get(..2).is_some_and(|p| p.eq_ignore_ascii_case(prefix)) {
+            return i128::from_str_radix(&s[2..], radix).ok().map(Num::Int);
+        }
+    }
+
+    let (mut negative, s) = match s.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        Some(inner) => (true, inner.trim()),
+        None => (false, s),
+    };
+    let mut s = s.strip_prefix('$').unwrap_or(s).trim_start();
+    if let Some(rest) = s.strip_prefix('-') {
+        negative = !negative;
+        s = rest;
+    } else if let Some(rest) = s.strip_prefix('+') {
+        s = rest;
+    }
+    let s = s.strip_prefix('$').unwrap_or(s);
+
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    let mantissa: String = mantissa.chars().filter(|&c| c != ',').collect();
+    if !mantissa.chars().any(|c| c.is_ascii_digit())
+        || !mantissa.chars().all(|c| c.is_ascii_digit() || c == '.')
+        || mantissa.matches('.').count() > 1
+    {
+        return None;
+    }
+    let exponent: i32 = match exponent {
+        Some(e) => e.parse().ok()?,
+        None => 0,
+    };
+    let sign = if negative { "-" } else { "" };
+    let text = format!("{}{}e{}", sign, mantissa, exponent);
+
+    Decimal::from_scientific(&text)
+        .ok()
+        .filter(|_| exponent.unsigned_abs() <= 28)
+        .map(Num::Dec)
+        .or_else(|| match exponent {
+            0 => Decimal::from_str(&format!("{}{}", sign, mantissa))
+                .ok()
+                .map(Num::Dec),
+            _ => None,
+        })
+        .or_else(|| {
+            text.parse::<f64>()
+                .ok()
+                .filter(|f| f.is_finite())
+                .map(Num::Float)
+        })
+}
+
+/// Parses a US English date and/or time, such as `"2024-03-01 14:30"` or `"3/1/2024 2:30 PM"`
+fn parse_date(s: &str) -> Option<NaiveDateTime> {
+    let mut date = None;
+    let mut time = None;
+    let mut pm = None;
+    for token in s
+        .split(|c: char| c.is_whitespace() || c == 'T')
+        .filter(|t| !t.is_empty())
+    {
+        let upper = token.to_ascii_uppercase();
+        let (token, meridiem) = match upper.strip_suffix("AM").or(upper.strip_suffix('A')) {
+            Some(rest) => (rest, Some(false)),
+            None => match upper.strip_suffix("PM").or(upper.strip_suffix('P')) {
+                Some(rest) => (rest, Some(true)),
+                None => (upper.as_str(), None),
+            },
+        };
+        if meridiem.is_some() {
+            if pm.is_some() {
+                return None;
+            }
+            pm = meridiem;
+        }
+        if token.is_empty() {
+            continue;
+        } else if token.contains(':') {
+            if time.replace(parse_time(token)?).is_some() {
+                return None;
+            }
+        } else if token.contains(['/', '-']) {
+            if date.replace(parse_ymd(token)?).is_some() {
+                return None;
+            }
+        } else if meridiem.is_some() && time.is_none() {
+            time = Some(NaiveTime::from_hms_opt(token.parse().ok()?, 0, 0)?);
+        } else {
+            return None;
+        }
+    }
+
+    let mut time = match (time, date) {
+        (None, None) => return None,
+        (time, _) => time.unwrap_or_default(),
+    };
+    if let Some(pm) = pm {
+        let hour = match (time.hour(), pm) {
+            (0 | 13.., _) => return None,
+            (12, false) => 0,
+            (12, true) => 12,
+            (h, false) => h,
+            (h, true) => h + 12,
+        };
+        time = time.with_hour(hour)?;
+    }
+    let date = date.unwrap_or_else(|| NaiveDate::from_ymd_opt(1899, 12, 30).unwrap());
+    Some(NaiveDateTime::new(date, time))
+}
+
+fn parse_ymd(s: &str) -> Option<NaiveDate> {
+    let parts: Vec<&str> = s.split(['/', '-']).collect();
+    let [a, b, c] = parts[..] else {
+        return None;
+    };
+    if a.len() == 4 {
+        NaiveDate::from_ymd_opt(a.parse().ok()?, b.parse().ok()?, c.parse().ok()?)
+    } else {
+        // two-digit years use the OLE window: 00-29 is 20xx and 30-99 is 19xx
+        let year: i32 = c.parse().ok()?;
+        let year = match (c.len(), year) {
+            (1 | 2, 0..=29) => year + 2000,
+            (1 | 2, _) => year + 1900,
+            _ => year,
+        };
+        NaiveDate::from_ymd_opt(year, a.parse().ok()?, b.parse().ok()?)
+    }
+}
+
+fn parse_time(s: &str) -> Option<NaiveTime> {
+    let parts: Vec<u32> = s
+        .split(':')
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    match parts[..] {
+        [h, m] => NaiveTime::from_hms_opt(h, m, 0),
+        [h, m, s] => NaiveTime::from_hms_opt(h, m, s),
+        _ => None,
+    }
+}
+
+/// Formats a value like `VariantChangeTypeEx`, which unlike `CStr` uses `-1` and `0` for booleans
+/// by default, and rounds floats to 15 (or 7) significant digits.
+fn format_value(
+    value: Variant<'static>,
+    flags: VAR_CHANGE_FLAGS,
+) -> Result<String, VariantConversionError> {
+    Ok(match value {
+        Variant::Bool(b) if (flags & (VARIANT_ALPHABOOL | VARIANT_LOCALBOOL)).0 == 0 => {
+            (if b { "-1" } else { "0" }).to_string()
+        }
+        Variant::F32(f) => format_float(&format!("{:.6e}", f), 7, '.'),
+        Variant::F64(f) => format_float(&format!("{:.14e}", f), 15, '.'),
+        value => format::format_value(value, &DisplaySettings::default())?,
+    })
+}
@@ -1,42 +1,108 @@
-//! Wrapper type for [`BOOL`]
+//! Wrapper type for [`VARIANT_BOOL`]
 
-#![allow(unused_imports)]
-use windows::Win32::Foundation::{BOOL, VARIANT_BOOL};
+use crate::convert::VariantConversionError;
+use std::hash::{Hash, Hasher};
+use windows::Win32::Foundation::VARIANT_BOOL;
 
-use enumn::N;
+/// Transparent wrapper around a COM boolean ([`VARIANT_BOOL`]) as stored in memory.
+///
+/// False is 0 and true is all ones (-1), but some servers write other values, so any nonzero value
+/// is read as true. [`ComBool::get_strict`] reports these instead. Values are compared and hashed
+/// as booleans.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(transparent)]
+pub struct ComBool(pub i16);
 
-/// Enum equivalent to COM [`BOOL`]. False is 0 and True is all ones (-1)
-#[derive(N, Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-#[repr(i16)]
-pub enum ComBool {
-    False = 0i16,
-    True = !0i16,
+impl ComBool {
+    pub const FALSE: ComBool = ComBool(0);
+    pub const TRUE: ComBool = ComBool(-1);
+
+    pub fn get(self) -> bool {
+        self.0 != 0
+    }
+
+    /// Fails with [`VariantConversionError::InvalidBool`] for values other than 0 and -1
+    pub fn get_strict(self) -> Result<bool, VariantConversionError> {
+        match self.0 {
+            0 => Ok(false),
+            -1 => Ok(true),
+            value => Err(VariantConversionError::InvalidBool(value)),
+        }
+    }
+
+    /// Stores 0 or -1
+    pub fn set(&mut self, value: bool) {
+        *self = value.into();
+    }
+
+    /// Whether the value is 0 or -1
+    pub fn is_canonical(self) -> bool {
+        matches!(self.0, 0 | -1)
+    }
+}
+
+impl PartialEq for ComBool {
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
+    }
+}
+
+impl Eq for ComBool {}
+
+impl PartialOrd for ComBool {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ComBool {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.get().cmp(&other.get())
+    }
+}
+
+impl Hash for ComBool {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.get().hash(state)
+    }
 }
 
-impl From<&mut i16> for &'static mut ComBool {
-    fn from(value: &mut i16) -> &'static mut ComBool {
+impl<'a> From<&'a mut i16> for &'a mut ComBool {
+    fn from(value: &'a mut i16) -> &'a mut ComBool {
         unsafe { &mut *(value as *mut i16 as *mut ComBool) }
     }
 }
 
-impl From<&mut VARIANT_BOOL> for &'static mut ComBool {
-    fn from(value: &mut VARIANT_BOOL) -> &'static mut ComBool {
-        unsafe { &mut *(value as *const VARIANT_BOOL as *mut ComBool) }
+impl<'a> From<&'a mut VARIANT_BOOL> for &'a mut ComBool {
+    fn from(value: &'a mut VARIANT_BOOL) -> &'a mut ComBool {
+        unsafe { &mut *(value as *mut VARIANT_BOOL as *mut ComBool) }
+    }
+}
+
+impl From<ComBool> for VARIANT_BOOL {
+    fn from(value: ComBool) -> VARIANT_BOOL {
+        VARIANT_BOOL(value.0)
+    }
+}
+
+impl From<VARIANT_BOOL> for ComBool {
+    fn from(value: VARIANT_BOOL) -> ComBool {
+        ComBool(value.0)
     }
 }
 
 impl From<ComBool> for bool {
     fn from(value: ComBool) -> bool {
-        value != ComBool::False
+        value.get()
     }
 }
 
 impl From<bool> for ComBool {
     fn from(value: bool) -> ComBool {
         if value {
-            ComBool::True
+            ComBool::TRUE
         } else {
-            ComBool::False
+            ComBool::FALSE
         }
     }
 }
@@ -1,15 +1,24 @@
 //! Wrapper type for [`CY`]
 
-use rust_decimal::Decimal;
+use crate::convert::VariantConversionError;
+use crate::sys::CY;
+use crate::VariantType;
+use rust_decimal::{Decimal, RoundingStrategy};
 use std::fmt::{Debug, Display};
-use windows::Win32::System::Com::CY;
+use std::ops::{Add, Div, Mul, Neg, Sub};
 
 /// Transparent wrapper around a [`CY`] value stored as an [`i64`]
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd)]
+#[repr(transparent)]
 pub struct ComCurrency(pub i64);
 
 /// Wrapper around COM [`CY`] using [`Decimal`].
+///
+/// The arithmetic operators follow `VarCyAdd`, `VarCySub` and `VarCyMul`: results are rounded to
+/// four decimal places with banker's rounding, and fail with
+/// [`VariantConversionError::Overflow`] when they don't fit in a [`CY`].
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Currency(pub Decimal);
 
 impl AsRef<Decimal> for Currency {
@@ -30,17 +39,47 @@ impl Debug for ComCurrency {
     }
 }
 
-impl From<Decimal> for ComCurrency {
-    fn from(cy: Decimal) -> Self {
-        let mut g = cy;
-        g.rescale(4);
-        ComCurrency(g.mantissa() as i64)
+fn overflow() -> VariantConversionError {
+    VariantConversionError::Overflow(VariantType::VT_CY)
+}
+
+impl ComCurrency {
+    pub const MIN: ComCurrency = ComCurrency(i64::MIN);
+    pub const MAX: ComCurrency = ComCurrency(i64::MAX);
+
+    /// Rounds the value to four decimal places with the given strategy, failing if it is out of
+    /// range
+    pub fn from_decimal(
+        value: Decimal,
+        strategy: RoundingStrategy,
+    ) -> Result<Self, VariantConversionError> {
+        let mut value = value.round_dp_with_strategy(4, strategy);
+        if value < Currency::MIN.0 || value > Currency::MAX.0 {
+            return Err(overflow());
+        }
+        value.rescale(4);
+        Ok(ComCurrency(value.mantissa() as i64))
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut CY {
+        (&mut self.0) as *mut i64 as *mut CY
     }
 }
 
-impl From<Currency> for ComCurrency {
-    fn from(cy: Currency) -> Self {
-        Self::from(cy.0)
+/// Rounds to four decimal places with banker's rounding, like `VarCyFromR8`
+impl TryFrom<Decimal> for ComCurrency {
+    type Error = VariantConversionError;
+
+    fn try_from(cy: Decimal) -> Result<Self, VariantConversionError> {
+        Self::from_decimal(cy, RoundingStrategy::MidpointNearestEven)
+    }
+}
+
+impl TryFrom<Currency> for ComCurrency {
+    type Error = VariantConversionError;
+
+    fn try_from(cy: Currency) -> Result<Self, VariantConversionError> {
+        Self::try_from(cy.0)
     }
 }
 
@@ -80,8 +119,86 @@ impl From<*mut CY> for &mut ComCurrency {
     }
 }
 
-impl ComCurrency {
-    pub fn as_mut_ptr(&mut self) -> *mut CY {
-        (&mut self.0) as *mut i64 as *mut CY
+impl Currency {
+    /// -922,337,203,685,477.5808
+    pub const MIN: Currency = Currency(Decimal::from_parts(0, 0x8000_0000, 0, true, 4));
+    /// 922,337,203,685,477.5807
+    pub const MAX: Currency = Currency(Decimal::from_parts(u32::MAX, 0x7FFF_FFFF, 0, false, 4));
+
+    /// Rounds the value to four decimal places with the given strategy, failing if it is out of
+    /// range
+    pub fn from_decimal(
+        value: Decimal,
+        strategy: RoundingStrategy,
+    ) -> Result<Self, VariantConversionError> {
+        ComCurrency::from_decimal(value, strategy).map(Currency::from)
+    }
+
+    /// Absolute value, like `VarCyAbs`
+    pub fn abs(self) -> Result<Self, VariantConversionError> {
+        Self::checked(Some(self.0.abs()))
+    }
+
+    /// Rounds to the given number of decimal places with banker's rounding, like `VarCyRound`
+    pub fn round(self, decimals: u32) -> Result<Self, VariantConversionError> {
+        Self::checked(Some(self.0.round_dp_with_strategy(
+            decimals,
+            RoundingStrategy::MidpointNearestEven,
+        )))
+    }
+
+    /// Integer part, rounded toward zero, like `VarCyFix`
+    pub fn fix(self) -> Self {
+        Currency(self.0.trunc())
+    }
+
+    fn checked(value: Option<Decimal>) -> Result<Self, VariantConversionError> {
+        Self::from_decimal(
+            value.ok_or_else(overflow)?,
+            RoundingStrategy::MidpointNearestEven,
+        )
+    }
+}
+
+impl Add for Currency {
+    type Output = Result<Currency, VariantConversionError>;
+
+    fn add(self, rhs: Currency) -> Self::Output {
+        Currency::checked(self.0.checked_add(rhs.0))
+    }
+}
+
+impl Sub for Currency {
+    type Output = Result<Currency, VariantConversionError>;
+
+    fn sub(self, rhs: Currency) -> Self::Output {
+        Currency::checked(self.0.checked_sub(rhs.0))
+    }
+}
+
+impl Mul for Currency {
+    type Output = Result<Currency, VariantConversionError>;
+
+    fn mul(self, rhs: Currency) -> Self::Output {
+        Currency::checked(self.0.checked_mul(rhs.0))
+    }
+}
+
+impl Div for Currency {
+    type Output = Result<Currency, VariantConversionError>;
+
+    fn div(self, rhs: Currency) -> Self::Output {
+        if rhs.0.is_zero() {
+            return Err(VariantConversionError::DivisionByZero);
+        }
+        Currency::checked(self.0.checked_div(rhs.0))
+    }
+}
+
+impl Neg for Currency {
+    type Output = Result<Currency, VariantConversionError>;
+
+    fn neg(self) -> Self::Output {
+        Currency::checked(Some(-self.0))
     }
 }
@@ -1,23 +1,41 @@
 //! Wrapper type for COM dates (stored as [`f64`])
 
-use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use crate::convert::VariantConversionError;
+use crate::VariantType;
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
 use std::fmt::{Debug, Display};
-use std::ops::Sub;
 
-/// Transparent wrapper around a COM date stored as an [`f64`]
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+/// Transparent wrapper around a COM date stored as an [`f64`].
+///
+/// The integer part is the number of days since 30 December 1899 and the fractional part is the
+/// time of day, which is positive even for earlier dates: `-1.25` is 29 December 1899 at 06:00.
+/// Valid dates range from 1 January 100 to 31 December 9999, and conversions round to the
+/// nearest millisecond like `VarUdateFromDate`.
 #[derive(Clone, Copy, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(transparent)]
 pub struct ComDate(pub f64);
 
+impl ComDate {
+    /// 1 January 100, at midnight. Later times on that day are further from zero, down to
+    /// `-657435.0` excluded.
+    pub const MIN: ComDate = ComDate(-657434.0);
+    /// 31 December 9999, 23:59:59.999
+    pub const MAX: ComDate = ComDate(2958465.0 + 86399999.0 / MILLIS_PER_DAY as f64);
+
+    pub fn as_mut_ptr(&mut self) -> *mut f64 {
+        (&mut self.0) as *mut f64
+    }
+}
+
 impl Display for ComDate {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match TryInto::<NaiveDateTime>::try_into(*self) {
-                Ok(s) => s.to_string(),
-                Err(_) => "<invalid>".to_owned(),
-            }
-        )
+        match NaiveDateTime::try_from(*self) {
+            Ok(date) => write!(f, "{}", date),
+            Err(_) => write!(f, "<invalid date {}>", self.0),
+        }
     }
 }
 
@@ -27,24 +45,68 @@ impl Debug for ComDate {
     }
 }
 
-macro_rules! com_epoch {
-    () => {
-        NaiveDateTime::new(
-            NaiveDate::from_ymd_opt(1899, 12, 30).unwrap(),
-            NaiveTime::from_hms_opt(0, 0, 0).unwrap(),
-        )
-    };
+fn com_epoch() -> NaiveDate {
+    NaiveDate::from_ymd_opt(1899, 12, 30).unwrap()
+}
+
+fn min_date() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(100, 1, 1)
+        .unwrap()
+        .and_time(NaiveTime::MIN)
+}
+
+fn max_date() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(9999, 12, 31)
+        .unwrap()
+        .and_hms_milli_opt(23, 59, 59, 999)
+        .unwrap()
+}
+
+fn overflow() -> VariantConversionError {
+    VariantConversionError::Overflow(VariantType::VT_DATE)
 }
 
-impl From<NaiveDateTime> for ComDate {
-    fn from(date: NaiveDateTime) -> Self {
-        ComDate(date.sub(com_epoch!()).num_milliseconds() as f64 / 24.0 / 60.0 / 60.0 / 1000.0)
+/// Fails for dates outside of the valid range, after rounding to the millisecond
+impl TryFrom<NaiveDateTime> for ComDate {
+    type Error = VariantConversionError;
+
+    fn try_from(date: NaiveDateTime) -> Result<Self, VariantConversionError> {
+        let nanos = date.nanosecond() % 1_000_000_000;
+        let date = date.with_nanosecond(0).unwrap()
+            + Duration::milliseconds(((nanos + 500_000) / 1_000_000) as i64);
+        if date < min_date() || date > max_date() {
+            return Err(overflow());
+        }
+        let days = (date.date() - com_epoch()).num_days();
+        let time = (date.time() - NaiveTime::MIN).num_milliseconds();
+        let time = time as f64 / MILLIS_PER_DAY as f64;
+        // the time is added away from zero, since it is always positive
+        Ok(ComDate(if days < 0 {
+            days as f64 - time
+        } else {
+            days as f64 + time
+        }))
     }
 }
 
-impl From<ComDate> for NaiveDateTime {
-    fn from(date: ComDate) -> Self {
-        com_epoch!() + Duration::milliseconds((date.0 * 24.0 * 60.0 * 60.0 * 1000.0) as i64)
+/// Fails for values outside of the valid range, after rounding to the millisecond
+impl TryFrom<ComDate> for NaiveDateTime {
+    type Error = VariantConversionError;
+
+    fn try_from(date: ComDate) -> Result<Self, VariantConversionError> {
+        // times on the first day are between MIN and MIN - 1
+        if !(date.0 > ComDate::MIN.0 - 1.0 && date.0 < ComDate::MAX.0.ceil()) {
+            return Err(overflow());
+        }
+        let days = date.0.trunc();
+        let time = ((date.0 - days).abs() * MILLIS_PER_DAY as f64).round() as i64;
+        let date = com_epoch().and_time(NaiveTime::MIN)
+            + Duration::days(days as i64)
+            + Duration::milliseconds(time);
+        if date < min_date() || date > max_date() {
+            return Err(overflow());
+        }
+        Ok(date)
     }
 }
 
@@ -53,9 +115,3 @@ impl From<*mut f64> for &mut ComDate {
         unsafe { &mut *(ptr as *mut ComDate) }
     }
 }
-
-impl ComDate {
-    pub fn as_mut_ptr(&mut self) -> *mut f64 {
-        (&mut self.0) as *mut f64
-    }
-}
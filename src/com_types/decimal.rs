@@ -1,14 +1,26 @@
 //! Wrapper type for [`DECIMAL`]
 
+use crate::convert::VariantConversionError;
+use crate::sys::{DECIMAL, DECIMAL_0, DECIMAL_0_0, DECIMAL_1};
 use crate::VariantType;
-use rust_decimal::Decimal;
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::cmp::Ordering;
 use std::fmt::{Debug, Display};
-use windows::Win32::Foundation::{DECIMAL, DECIMAL_0, DECIMAL_0_0, DECIMAL_1};
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, Div, Mul, Sub};
 
 const DECIMAL_NEG: u8 = 0x80;
+const MAX_SCALE: u8 = 28;
 
-/// Transparent wrapper around a [`DECIMAL`] value
+/// Transparent wrapper around a [`DECIMAL`] value.
+///
+/// Values are compared and hashed by their numeric value, so `1.0 == 1.00`. Invalid values, with
+/// a scale above 28 or reserved sign bits set, are only equal to identical values and sort after
+/// all valid ones. The arithmetic operators follow `VarDecAdd`, `VarDecSub`, `VarDecMul` and
+/// `VarDecDiv`: results that need more than 96 bits are rounded to fewer decimal places, and fail
+/// with [`VariantConversionError::Overflow`] when their integer part doesn't fit.
 #[derive(Copy, Clone)]
+#[repr(transparent)]
 pub struct ComDecimal(pub DECIMAL);
 
 // seriously guys, why did you remove impl PartialEq for DECIMAL??
@@ -24,15 +36,45 @@ fn dec_to_bytes(dec: &DECIMAL) -> &[u8] {
 
 impl PartialEq for ComDecimal {
     fn eq(&self, other: &Self) -> bool {
-        dec_to_bytes(&self.0) == dec_to_bytes(&other.0)
+        self.cmp(other) == Ordering::Equal
     }
 }
 
 impl Eq for ComDecimal {}
 
+impl PartialOrd for ComDecimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compares values like `VarDecCmp`
+impl Ord for ComDecimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (Decimal::try_from(self), Decimal::try_from(other)) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(_), Err(_)) => dec_to_bytes(&self.0).cmp(dec_to_bytes(&other.0)),
+        }
+    }
+}
+
+impl Hash for ComDecimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match Decimal::try_from(self) {
+            Ok(d) => d.hash(state),
+            Err(_) => dec_to_bytes(&self.0).hash(state),
+        }
+    }
+}
+
 impl Display for ComDecimal {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", Into::<Decimal>::into(self))
+        match Decimal::try_from(self) {
+            Ok(d) => write!(f, "{}", d),
+            Err(_) => write!(f, "<invalid decimal>"),
+        }
     }
 }
 
@@ -71,24 +113,38 @@ impl From<Decimal> for ComDecimal {
     }
 }
 
-impl From<&ComDecimal> for Decimal {
-    fn from(dec: &ComDecimal) -> Self {
+/// Fails with [`VariantConversionError::InvalidDecimal`] if the scale is above 28 or reserved sign
+/// bits are set
+impl TryFrom<&ComDecimal> for Decimal {
+    type Error = VariantConversionError;
+
+    fn try_from(dec: &ComDecimal) -> Result<Self, VariantConversionError> {
         let num = dec.0;
-        unsafe {
-            Decimal::from_parts(
-                (num.Anonymous2.Lo64 & 0xFFFFFFFF) as u32,
-                ((num.Anonymous2.Lo64 >> 32) & 0xFFFFFFFF) as u32,
-                num.Hi32,
-                num.Anonymous1.Anonymous.sign == DECIMAL_NEG,
-                num.Anonymous1.Anonymous.scale as u32,
+        let (scale, sign) = unsafe {
+            (
+                num.Anonymous1.Anonymous.scale,
+                num.Anonymous1.Anonymous.sign,
             )
+        };
+        if scale > MAX_SCALE || sign & !DECIMAL_NEG != 0 {
+            return Err(VariantConversionError::InvalidDecimal);
         }
+        let lo64 = unsafe { num.Anonymous2.Lo64 };
+        Ok(Decimal::from_parts(
+            (lo64 & 0xFFFFFFFF) as u32,
+            ((lo64 >> 32) & 0xFFFFFFFF) as u32,
+            num.Hi32,
+            sign == DECIMAL_NEG,
+            scale as u32,
+        ))
     }
 }
 
-impl From<ComDecimal> for Decimal {
-    fn from(dec: ComDecimal) -> Self {
-        Self::from(&dec)
+impl TryFrom<ComDecimal> for Decimal {
+    type Error = VariantConversionError;
+
+    fn try_from(dec: ComDecimal) -> Result<Self, VariantConversionError> {
+        Self::try_from(&dec)
     }
 }
 
@@ -98,8 +154,90 @@ impl From<*mut DECIMAL> for &mut ComDecimal {
     }
 }
 
+fn overflow() -> VariantConversionError {
+    VariantConversionError::Overflow(VariantType::VT_DECIMAL)
+}
+
+/// Applies an operation to the values of two decimals
+fn apply(
+    a: ComDecimal,
+    b: ComDecimal,
+    op: impl FnOnce(Decimal, Decimal) -> Option<Decimal>,
+) -> Result<ComDecimal, VariantConversionError> {
+    let res = op(Decimal::try_from(a)?, Decimal::try_from(b)?);
+    res.map(ComDecimal::from).ok_or_else(overflow)
+}
+
 impl ComDecimal {
     pub fn as_mut_ptr(&mut self) -> *mut DECIMAL {
         (&mut self.0) as *mut DECIMAL
     }
+
+    /// Rounds to the given number of decimal places with banker's rounding, like `VarDecRound`
+    pub fn round(self, decimals: u32) -> Result<Self, VariantConversionError> {
+        Ok(Decimal::try_from(self)?
+            .round_dp_with_strategy(decimals, RoundingStrategy::MidpointNearestEven)
+            .into())
+    }
+
+    /// Integer part, rounded toward zero, like `VarDecFix`
+    pub fn fix(self) -> Result<Self, VariantConversionError> {
+        Ok(Decimal::try_from(self)?.trunc().into())
+    }
+
+    /// Largest integer less than or equal to the value, like `VarDecInt`
+    pub fn int(self) -> Result<Self, VariantConversionError> {
+        Ok(Decimal::try_from(self)?.floor().into())
+    }
+}
+
+impl Add for ComDecimal {
+    type Output = Result<ComDecimal, VariantConversionError>;
+
+    fn add(self, rhs: ComDecimal) -> Self::Output {
+        apply(self, rhs, |a, b| a.checked_add(b))
+    }
+}
+
+impl Sub for ComDecimal {
+    type Output = Result<ComDecimal, VariantConversionError>;
+
+    fn sub(self, rhs: ComDecimal) -> Self::Output {
+        apply(self, rhs, |a, b| a.checked_sub(b))
+    }
+}
+
+impl Mul for ComDecimal {
+    type Output = Result<ComDecimal, VariantConversionError>;
+
+    fn mul(self, rhs: ComDecimal) -> Self::Output {
+        apply(self, rhs, |a, b| a.checked_mul(b))
+    }
+}
+
+impl Div for ComDecimal {
+    type Output = Result<ComDecimal, VariantConversionError>;
+
+    fn div(self, rhs: ComDecimal) -> Self::Output {
+        if Decimal::try_from(rhs)?.is_zero() {
+            return Err(VariantConversionError::DivisionByZero);
+        }
+        apply(self, rhs, |a, b| a.checked_div(b))
+    }
+}
+
+/// Serialized as the equivalent [`Decimal`]
+#[cfg(feature = "serde")]
+impl serde::Serialize for ComDecimal {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let dec = Decimal::try_from(self).map_err(serde::ser::Error::custom)?;
+        serde::Serialize::serialize(&dec, serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ComDecimal {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <Decimal as serde::Deserialize>::deserialize(deserializer).map(ComDecimal::from)
+    }
 }
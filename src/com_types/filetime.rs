@@ -0,0 +1,77 @@
+//! Wrapper type for [`FILETIME`] timestamps
+
+use crate::convert::VariantConversionError;
+use crate::VariantType;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use std::fmt::{Debug, Display};
+use windows::Win32::Foundation::FILETIME;
+
+const TICKS_PER_SECOND: u64 = 10_000_000;
+
+/// Transparent wrapper around a [`FILETIME`], which counts 100-nanosecond intervals since
+/// 1 January 1601 (UTC)
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct ComFileTime(pub FILETIME);
+
+impl ComFileTime {
+    pub fn ticks(self) -> u64 {
+        (self.0.dwHighDateTime as u64) << 32 | self.0.dwLowDateTime as u64
+    }
+
+    pub fn from_ticks(ticks: u64) -> Self {
+        ComFileTime(FILETIME {
+            dwLowDateTime: ticks as u32,
+            dwHighDateTime: (ticks >> 32) as u32,
+        })
+    }
+}
+
+impl Display for ComFileTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", NaiveDateTime::from(*self))
+    }
+}
+
+impl Debug for ComFileTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ComFileTime({}, {})", self.ticks(), self)
+    }
+}
+
+fn epoch() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(1601, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+}
+
+/// Dates before 1601 can't be represented
+impl TryFrom<NaiveDateTime> for ComFileTime {
+    type Error = VariantConversionError;
+
+    fn try_from(date: NaiveDateTime) -> Result<Self, VariantConversionError> {
+        let elapsed = date - epoch();
+        if elapsed < Duration::zero() {
+            return Err(VariantConversionError::Overflow(VariantType::VT_FILETIME));
+        }
+        let seconds = elapsed.num_seconds() as u64;
+        let nanos = (elapsed - Duration::seconds(elapsed.num_seconds()))
+            .num_nanoseconds()
+            .unwrap() as u64;
+        seconds
+            .checked_mul(TICKS_PER_SECOND)
+            .and_then(|ticks| ticks.checked_add(nanos / 100))
+            .map(ComFileTime::from_ticks)
+            .ok_or(VariantConversionError::Overflow(VariantType::VT_FILETIME))
+    }
+}
+
+impl From<ComFileTime> for NaiveDateTime {
+    fn from(time: ComFileTime) -> Self {
+        let ticks = time.ticks();
+        epoch()
+            + Duration::seconds((ticks / TICKS_PER_SECOND) as i64)
+            + Duration::nanoseconds((ticks % TICKS_PER_SECOND * 100) as i64)
+    }
+}
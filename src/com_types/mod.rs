@@ -1,11 +1,15 @@
-//! Utilities for handling COM types ([`BOOL`], [`CY`], [`DECIMAL`], etc.)
+//! Utilities for handling COM types ([`BOOL`], [`CY`], [`DECIMAL`], [`SAFEARRAY`], etc.)
 
 #![allow(unused_imports)]
-use windows::Win32::Foundation::{BOOL, DECIMAL};
-use windows::Win32::System::Com::CY;
+use crate::sys::{CY, DECIMAL};
+use windows::Win32::Foundation::BOOL;
+use windows::Win32::System::Com::SAFEARRAY;
 
 pub mod bool;
 pub mod currency;
 pub mod date;
 pub mod decimal;
+pub mod filetime;
 pub mod ptr_wrapper;
+pub mod record;
+pub mod safearray;
@@ -2,30 +2,32 @@
 
 use std::fmt::Debug;
 
-pub struct PtrWrapper<T: 'static>(pub &'static mut T);
+pub struct PtrWrapper<'a, T>(pub &'a mut T);
 
-impl<T: 'static> Debug for PtrWrapper<T> {
+impl<'a, T> Debug for PtrWrapper<'a, T> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "PtrWrapper({:p})", self.0)
     }
 }
 
-impl<T: 'static> PartialEq for PtrWrapper<T> {
-    fn eq(&self, other: &PtrWrapper<T>) -> bool {
+impl<'a, T> PartialEq for PtrWrapper<'a, T> {
+    fn eq(&self, other: &PtrWrapper<'a, T>) -> bool {
         std::ptr::eq(self.0, other.0)
     }
 }
 
-impl<T: 'static> TryFrom<&*mut T> for PtrWrapper<T> {
-    type Error = ();
-
-    fn try_from(ptr: &*mut T) -> Result<Self, Self::Error> {
-        unsafe { ptr.as_mut() }.map(|ptr| PtrWrapper(ptr)).ok_or(())
+impl<'a, T> PtrWrapper<'a, T> {
+    /// Wraps a raw pointer, returning [`None`] if it is null.
+    ///
+    /// # Safety
+    /// `ptr` must be null or valid for reads and writes during `'a`, and must not be aliased.
+    pub unsafe fn from_raw(ptr: *mut T) -> Option<Self> {
+        ptr.as_mut().map(PtrWrapper)
     }
 }
 
-impl<T: 'static> From<PtrWrapper<T>> for *mut T {
-    fn from(ptr: PtrWrapper<T>) -> Self {
+impl<'a, T> From<PtrWrapper<'a, T>> for *mut T {
+    fn from(ptr: PtrWrapper<'a, T>) -> Self {
         ptr.0
     }
 }
@@ -0,0 +1,203 @@
+//! Wrapper types for COM user-defined types (`VT_RECORD`)
+
+use crate::convert::VariantConversionError;
+use crate::sys::{BSTR, VARIANT};
+use crate::{OwnedVariant, Variant, VariantType};
+use std::ffi::c_void;
+use windows::core::{GUID, PCWSTR};
+use windows::Win32::System::Com::INVOKE_PROPERTYPUT;
+use windows::Win32::System::Ole::IRecordInfo;
+
+/// Identity of a user-defined type, as described by its [`IRecordInfo`]
+#[derive(Clone, Debug)]
+pub struct RecordType {
+    pub name: String,
+    pub guid: GUID,
+    /// Type information used to allocate the record when converting to a [`VARIANT`]
+    pub info: Option<IRecordInfo>,
+}
+
+impl PartialEq for RecordType {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.guid == other.guid
+    }
+}
+
+impl RecordType {
+    pub fn new(name: impl Into<String>, guid: GUID) -> Self {
+        RecordType {
+            name: name.into(),
+            guid,
+            info: None,
+        }
+    }
+
+    #[cfg(not(all(feature = "portable", not(windows))))]
+    pub fn from_info(info: IRecordInfo) -> Result<Self, VariantConversionError> {
+        unsafe {
+            Ok(RecordType {
+                name: info.GetName()?.to_string(),
+                guid: info.GetGuid()?,
+                info: Some(info),
+            })
+        }
+    }
+}
+
+/// Owned copy of a COM user-defined type, stored as an ordered list of named fields. Fields never
+/// borrow, since they are copied out of the record.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub record_type: RecordType,
+    pub fields: Vec<(String, Variant<'static>)>,
+}
+
+impl Record {
+    pub fn new(record_type: RecordType, fields: Vec<(String, Variant<'static>)>) -> Self {
+        Record {
+            record_type,
+            fields,
+        }
+    }
+
+    /// Clones the record, failing like [`Variant::try_clone`] if a field can't be cloned
+    pub fn try_clone(&self) -> Result<Self, VariantConversionError> {
+        Ok(Record {
+            record_type: self.record_type.clone(),
+            fields: self
+                .fields
+                .iter()
+                .map(|(name, value)| Ok((name.clone(), value.try_clone()?)))
+                .collect::<Result<_, VariantConversionError>>()?,
+        })
+    }
+
+    /// Gets a field by name. Like in Visual Basic, the name is case-insensitive.
+    pub fn get(&self, name: &str) -> Option<&Variant<'static>> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Variant<'static>> {
+        self.fields
+            .iter_mut()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v)
+    }
+
+    /// Removes a field by name and returns its value. The name is case-insensitive.
+    pub fn take(&mut self, name: &str) -> Option<Variant<'static>> {
+        let index = self
+            .fields
+            .iter()
+            .position(|(n, _)| n.eq_ignore_ascii_case(name))?;
+        Some(self.fields.remove(index).1)
+    }
+
+    /// Reads all the fields of a record, without taking ownership of it.
+    ///
+    /// With the `portable` backend, the fields can't be read through the [`IRecordInfo`], and this
+    /// fails with [`VariantConversionError::Unimplemented`].
+    ///
+    /// # Safety
+    /// `data` must point to a valid record described by `info`.
+    pub unsafe fn from_raw(
+        data: *const c_void,
+        info: Option<&IRecordInfo>,
+    ) -> Result<Self, VariantConversionError> {
+        let info = info.ok_or(VariantConversionError::MissingRecordInfo)?;
+        if data.is_null() {
+            return Err(VariantConversionError::GenericConversionError);
+        }
+        read_fields(data, info)
+    }
+
+    /// Allocates a record using the [`IRecordInfo`] of its type and fills its fields. The caller
+    /// is responsible for destroying the returned record.
+    ///
+    /// With the `portable` backend, the fields can't be written through the [`IRecordInfo`], and
+    /// this fails with [`VariantConversionError::Unimplemented`].
+    pub fn into_raw(self) -> Result<(*mut c_void, IRecordInfo), VariantConversionError> {
+        let info = self
+            .record_type
+            .info
+            .ok_or(VariantConversionError::MissingRecordInfo)?;
+        unsafe { write_fields(self.fields, info) }
+    }
+}
+
+#[cfg(not(all(feature = "portable", not(windows))))]
+unsafe fn read_fields(
+    data: *const c_void,
+    info: &IRecordInfo,
+) -> Result<Record, VariantConversionError> {
+    let mut count = 0;
+    info.GetFieldNames(&mut count, std::ptr::null_mut())?;
+    let mut names = vec![BSTR::default(); count as usize];
+    info.GetFieldNames(&mut count, names.as_mut_ptr())?;
+
+    let fields = names
+        .into_iter()
+        .map(|name| {
+            let field = info.GetField(data, PCWSTR(name.as_wide().as_ptr()))?;
+            Ok((name.to_string(), field.try_into()?))
+        })
+        .collect::<Result<_, VariantConversionError>>()?;
+
+    Ok(Record {
+        record_type: RecordType::from_info(info.clone())?,
+        fields,
+    })
+}
+
+#[cfg(not(all(feature = "portable", not(windows))))]
+unsafe fn write_fields(
+    fields: Vec<(String, Variant<'static>)>,
+    info: IRecordInfo,
+) -> Result<(*mut c_void, IRecordInfo), VariantConversionError> {
+    let data = info.RecordCreate();
+    if data.is_null() {
+        return Err(VariantConversionError::GenericConversionError);
+    }
+    for (name, value) in fields {
+        let res = OwnedVariant::try_from(value).and_then(|field| {
+            let name = BSTR::from(name);
+            info.PutField(
+                INVOKE_PROPERTYPUT.0 as u32,
+                data,
+                PCWSTR(name.as_wide().as_ptr()),
+                field.as_ptr(),
+            )
+            .map_err(Into::into)
+        });
+        if let Err(e) = res {
+            let _ = info.RecordDestroy(data);
+            return Err(e);
+        }
+    }
+    Ok((data, info))
+}
+
+// the IRecordInfo methods report errors through oleaut32, and use its BSTRs and VARIANTs, so
+// records can only be built, compared and serialized on the Rust side
+#[cfg(all(feature = "portable", not(windows)))]
+unsafe fn read_fields(
+    _data: *const c_void,
+    _info: &IRecordInfo,
+) -> Result<Record, VariantConversionError> {
+    Err(VariantConversionError::Unimplemented(
+        VariantType::VT_RECORD,
+    ))
+}
+
+#[cfg(all(feature = "portable", not(windows)))]
+unsafe fn write_fields(
+    _fields: Vec<(String, Variant<'static>)>,
+    _info: IRecordInfo,
+) -> Result<(*mut c_void, IRecordInfo), VariantConversionError> {
+    Err(VariantConversionError::Unimplemented(
+        VariantType::VT_RECORD,
+    ))
+}
@@ -0,0 +1,348 @@
+//! Wrapper types for [`SAFEARRAY`]
+
+use crate::convert::VariantConversionError;
+use crate::sys::{SafeArrayCreate, SafeArrayDestroy, BSTR, DECIMAL, VARIANT};
+use crate::{variant, OwnedVariant, PtrWrapper, Variant, VariantType};
+use std::mem::size_of;
+use windows::core::HRESULT;
+use windows::Win32::Foundation::VARIANT_BOOL;
+use windows::Win32::System::Com::{SAFEARRAY, SAFEARRAYBOUND};
+use windows::Win32::System::Variant::VARENUM;
+
+/// Bounds of a single [`SafeArray`] dimension
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SafeArrayBound {
+    pub lower: i32,
+    pub len: u32,
+}
+
+impl SafeArrayBound {
+    pub fn new(lower: i32, len: u32) -> Self {
+        SafeArrayBound { lower, len }
+    }
+}
+
+impl From<SAFEARRAYBOUND> for SafeArrayBound {
+    fn from(bound: SAFEARRAYBOUND) -> Self {
+        SafeArrayBound::new(bound.lLbound, bound.cElements)
+    }
+}
+
+impl From<SafeArrayBound> for SAFEARRAYBOUND {
+    fn from(bound: SafeArrayBound) -> Self {
+        SAFEARRAYBOUND {
+            cElements: bound.len,
+            lLbound: bound.lower,
+        }
+    }
+}
+
+/// Owned, multi-dimensional array of [`Variant`] values, equivalent to a COM [`SAFEARRAY`]
+///
+/// Dimensions are listed in the order expected by `SafeArrayCreate` and indexed the same way as
+/// in Visual Basic. Elements are stored in [`SAFEARRAY`] memory order, i.e. the first dimension
+/// varies fastest. An array without any dimension represents a null [`SAFEARRAY`] pointer.
+/// Elements never borrow, since they are moved out of the [`SAFEARRAY`].
+#[derive(Debug, PartialEq)]
+pub struct SafeArray {
+    element_type: VariantType,
+    bounds: Vec<SafeArrayBound>,
+    elements: Vec<Variant<'static>>,
+}
+
+impl SafeArray {
+    /// Creates an array after checking that the element type is valid and that the number of
+    /// elements matches the bounds.
+    pub fn new(
+        element_type: VariantType,
+        bounds: Vec<SafeArrayBound>,
+        elements: Vec<Variant<'static>>,
+    ) -> Result<Self, VariantConversionError> {
+        element_size(element_type)?;
+        let expected = if bounds.is_empty() {
+            0
+        } else {
+            bounds.iter().map(|b| b.len as usize).product()
+        };
+        if elements.len() != expected {
+            return Err(VariantConversionError::ArrayBoundsMismatch);
+        }
+        Ok(SafeArray {
+            element_type,
+            bounds,
+            elements,
+        })
+    }
+
+    /// Creates a zero-based, one-dimensional array of `VT_VARIANT` elements
+    pub fn from_vec(elements: Vec<Variant<'static>>) -> Self {
+        SafeArray {
+            element_type: VariantType::VT_VARIANT,
+            bounds: vec![SafeArrayBound::new(0, elements.len() as u32)],
+            elements,
+        }
+    }
+
+    pub fn element_type(&self) -> VariantType {
+        self.element_type
+    }
+
+    pub fn bounds(&self) -> &[SafeArrayBound] {
+        &self.bounds
+    }
+
+    pub fn elements(&self) -> &[Variant<'static>] {
+        &self.elements
+    }
+
+    pub fn into_elements(self) -> Vec<Variant<'static>> {
+        self.elements
+    }
+
+    /// Clones the array, failing like [`Variant::try_clone`] if an element can't be cloned
+    pub fn try_clone(&self) -> Result<Self, VariantConversionError> {
+        Ok(SafeArray {
+            element_type: self.element_type,
+            bounds: self.bounds.clone(),
+            elements: self
+                .elements
+                .iter()
+                .map(Variant::try_clone)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    fn offset(&self, indices: &[i32]) -> Option<usize> {
+        if indices.len() != self.bounds.len() {
+            return None;
+        }
+        let mut offset = 0;
+        let mut stride = 1;
+        for (&index, bound) in indices.iter().zip(&self.bounds) {
+            let rel = usize::try_from(index.checked_sub(bound.lower)?).ok()?;
+            if rel >= bound.len as usize {
+                return None;
+            }
+            offset += rel * stride;
+            stride *= bound.len as usize;
+        }
+        Some(offset)
+    }
+
+    /// Gets the element at the specified indices, one per dimension
+    pub fn get(&self, indices: &[i32]) -> Option<&Variant<'static>> {
+        self.offset(indices).map(|i| &self.elements[i])
+    }
+
+    pub fn get_mut(&mut self, indices: &[i32]) -> Option<&mut Variant<'static>> {
+        self.offset(indices).map(|i| &mut self.elements[i])
+    }
+
+    /// Takes ownership of a [`SAFEARRAY`] and moves its elements out, then destroys it. Fails with
+    /// [`VariantConversionError::ComError`] if it can't be destroyed, for example with
+    /// `DISP_E_ARRAYISLOCKED` if it is locked.
+    ///
+    /// # Safety
+    /// `psa` must be null or point to a valid [`SAFEARRAY`] of `element_type` elements, owned by
+    /// the caller.
+    pub unsafe fn from_raw(
+        psa: *mut SAFEARRAY,
+        element_type: VariantType,
+    ) -> Result<Self, VariantConversionError> {
+        let res = Self::read_raw(psa, element_type, true);
+        let destroyed = destroy(psa);
+        let array = res?;
+        destroyed.map_err(VariantConversionError::ComError)?;
+        Ok(array)
+    }
+
+    /// Copies the elements of a [`SAFEARRAY`], without taking ownership of it. Strings are
+    /// duplicated and interfaces are `AddRef`'d, like `SafeArrayCopy` does.
+    ///
+    /// # Safety
+    /// `psa` must be null or point to a valid [`SAFEARRAY`] of `element_type` elements.
+    pub unsafe fn copy_from_raw(
+        psa: *const SAFEARRAY,
+        element_type: VariantType,
+    ) -> Result<Self, VariantConversionError> {
+        Self::read_raw(psa, element_type, false)
+    }
+
+    unsafe fn read_raw(
+        psa: *const SAFEARRAY,
+        element_type: VariantType,
+        take: bool,
+    ) -> Result<Self, VariantConversionError> {
+        let size = element_size(element_type)?;
+        // like in SafeArray::new, an array without bounds has no elements
+        let Some(arr) = psa.as_ref().filter(|arr| arr.cDims != 0) else {
+            return Ok(SafeArray {
+                element_type,
+                bounds: vec![],
+                elements: vec![],
+            });
+        };
+
+        let dims = arr.cDims as usize;
+        // bounds are stored in reverse order
+        let bounds: Vec<SafeArrayBound> = std::slice::from_raw_parts(arr.rgsabound.as_ptr(), dims)
+            .iter()
+            .rev()
+            .map(|&b| b.into())
+            .collect();
+        let count = bounds.iter().map(|b| b.len as usize).product();
+
+        let stride = arr.cbElements as usize;
+        let data = arr.pvData as *mut u8;
+        let mut elements = Vec::with_capacity(count);
+        for i in 0..count {
+            let ptr = data.add(i * stride);
+            let element = read_element(ptr, element_type, size, take);
+            if take {
+                // the element now belongs to us, so the array must not free it
+                ptr.write_bytes(0, stride);
+            }
+            elements.push(element?);
+        }
+
+        Ok(SafeArray {
+            element_type,
+            bounds,
+            elements,
+        })
+    }
+
+    /// Allocates a [`SAFEARRAY`] and moves the elements into it. The caller is responsible for
+    /// destroying the returned array.
+    pub fn into_raw(self) -> Result<*mut SAFEARRAY, VariantConversionError> {
+        let size = element_size(self.element_type)?;
+        if self.bounds.is_empty() {
+            return Ok(std::ptr::null_mut());
+        }
+
+        let bounds: Vec<SAFEARRAYBOUND> = self.bounds.iter().map(|&b| b.into()).collect();
+        unsafe {
+            let psa = SafeArrayCreate(
+                VARENUM(self.element_type as u16),
+                bounds.len() as u32,
+                bounds.as_ptr(),
+            );
+            let arr = psa
+                .as_ref()
+                .ok_or(VariantConversionError::GenericConversionError)?;
+            let stride = arr.cbElements as usize;
+            let data = arr.pvData as *mut u8;
+            for (i, element) in self.elements.into_iter().enumerate() {
+                if let Err(e) =
+                    write_element(data.add(i * stride), self.element_type, size, element)
+                {
+                    let _ = SafeArrayDestroy(psa);
+                    return Err(e);
+                }
+            }
+            Ok(psa)
+        }
+    }
+}
+
+/// Non-owned pointer to a [`SAFEARRAY`] pointer, as found in `VT_ARRAY | VT_BYREF` variants
+#[derive(Debug, PartialEq)]
+pub struct SafeArrayPtr<'a> {
+    pub element_type: VariantType,
+    pub ptr: PtrWrapper<'a, *mut SAFEARRAY>,
+}
+
+/// Destroys an array with [`SafeArrayDestroy`], which fails if the array is locked
+#[cfg(not(all(feature = "portable", not(windows))))]
+unsafe fn destroy(psa: *mut SAFEARRAY) -> Result<(), HRESULT> {
+    SafeArrayDestroy(psa).map_err(|e| e.code())
+}
+
+#[cfg(all(feature = "portable", not(windows)))]
+unsafe fn destroy(psa: *mut SAFEARRAY) -> Result<(), HRESULT> {
+    let hr = SafeArrayDestroy(psa);
+    if hr.is_ok() {
+        Ok(())
+    } else {
+        Err(hr)
+    }
+}
+
+pub(crate) fn element_size(element_type: VariantType) -> Result<usize, VariantConversionError> {
+    use VariantType::*;
+    Ok(match element_type {
+        VT_I1 | VT_UI1 => 1,
+        VT_I2 | VT_UI2 => 2,
+        VT_BOOL => size_of::<VARIANT_BOOL>(),
+        VT_I4 | VT_UI4 | VT_INT | VT_UINT | VT_R4 | VT_ERROR => 4,
+        VT_I8 | VT_UI8 | VT_R8 | VT_CY | VT_DATE => 8,
+        VT_BSTR => size_of::<BSTR>(),
+        VT_DISPATCH | VT_UNKNOWN => size_of::<usize>(),
+        VT_DECIMAL => size_of::<DECIMAL>(),
+        VT_VARIANT => size_of::<VARIANT>(),
+        _ => return Err(VariantConversionError::InvalidArrayType(element_type)),
+    })
+}
+
+/// Reads an element either by taking ownership of it, or by copying it
+unsafe fn read_element(
+    ptr: *const u8,
+    element_type: VariantType,
+    size: usize,
+    take: bool,
+) -> Result<Variant<'static>, VariantConversionError> {
+    let var = match element_type {
+        VariantType::VT_VARIANT => (ptr as *const VARIANT).read_unaligned(),
+        VariantType::VT_DECIMAL => {
+            let mut var: VARIANT = variant!(
+                VariantType::VT_DECIMAL,
+                (decVal),
+                (ptr as *const DECIMAL).read_unaligned()
+            );
+            (*var.Anonymous.Anonymous).vt = VARENUM(VariantType::VT_DECIMAL as u16);
+            var
+        }
+        _ => {
+            let mut var: VARIANT = variant!(element_type);
+            let dst = &mut (*var.Anonymous.Anonymous).Anonymous as *mut _ as *mut u8;
+            ptr.copy_to_nonoverlapping(dst, size);
+            var
+        }
+    };
+    if take {
+        var.try_into()
+    } else {
+        Variant::try_from(&var)
+    }
+}
+
+unsafe fn write_element(
+    ptr: *mut u8,
+    element_type: VariantType,
+    size: usize,
+    element: Variant<'static>,
+) -> Result<(), VariantConversionError> {
+    let var: VARIANT = element.try_into()?;
+    match element_type {
+        VariantType::VT_VARIANT => (ptr as *mut VARIANT).write_unaligned(var),
+        _ if var.Anonymous.Anonymous.vt.0 != element_type as u16 => {
+            drop(OwnedVariant::from_raw(var));
+            return Err(VariantConversionError::ArrayElementMismatch(element_type));
+        }
+        VariantType::VT_DECIMAL => (ptr as *mut DECIMAL).write_unaligned(var.Anonymous.decVal),
+        _ => {
+            let src = &var.Anonymous.Anonymous.Anonymous as *const _ as *const u8;
+            src.copy_to_nonoverlapping(ptr, size);
+        }
+    }
+    Ok(())
+}
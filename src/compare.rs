@@ -0,0 +1,185 @@
+//! Comparison of [`Variant`] values, following the rules of `VarCmp`
+
+use crate::coerce::{to_decimal, to_float, Num};
+use crate::com_types::date::ComDate;
+use crate::convert::VariantConversionError;
+use crate::{Variant, VariantType};
+use rust_decimal::Decimal;
+use std::cmp::Ordering;
+
+/// Result of [`Variant::compare`], equivalent to the `VARCMP_*` values
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VariantOrdering {
+    Less,
+    Equal,
+    Greater,
+    /// Either value is `Null`
+    Null,
+}
+
+impl VariantOrdering {
+    /// Gets the equivalent [`Ordering`], or `None` for [`VariantOrdering::Null`]
+    pub fn to_ordering(self) -> Option<Ordering> {
+        match self {
+            VariantOrdering::Less => Some(Ordering::Less),
+            VariantOrdering::Equal => Some(Ordering::Equal),
+            VariantOrdering::Greater => Some(Ordering::Greater),
+            VariantOrdering::Null => None,
+        }
+    }
+}
+
+impl From<Ordering> for VariantOrdering {
+    fn from(ord: Ordering) -> Self {
+        match ord {
+            Ordering::Less => VariantOrdering::Less,
+            Ordering::Equal => VariantOrdering::Equal,
+            Ordering::Greater => VariantOrdering::Greater,
+        }
+    }
+}
+
+/// String comparison options for [`Variant::compare`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CompareOptions {
+    /// Compares strings case-insensitively, like `NORM_IGNORECASE`
+    pub ignore_case: bool,
+}
+
+/// Value of a variant as seen by the comparison
+enum Key {
+    Null,
+    Empty,
+    Num(Num),
+    Str(Vec<u16>),
+}
+
+/// Number of days since the OLE epoch. Unlike the OLE value, it increases with time before the
+/// epoch too, where the time of day is counted away from zero (-1.25 is earlier than -1.75).
+fn days(date: ComDate) -> f64 {
+    let days = date.0.trunc();
+    days + (date.0 - days).abs()
+}
+
+fn key(value: &Variant) -> Result<Key, VariantConversionError> {
+    Ok(match value {
+        Variant::Null => Key::Null,
+        Variant::Empty => Key::Empty,
+        Variant::Bool(b) => Key::Num(Num::Int(if *b { -1 } else { 0 })),
+        Variant::BoolRef(b) => Key::Num(Num::Int(if b.get() { -1 } else { 0 })),
+        Variant::I8(i) => Key::Num(Num::Int((*i).into())),
+        Variant::I8Ref(i) => Key::Num(Num::Int((**i).into())),
+        Variant::I16(i) => Key::Num(Num::Int((*i).into())),
+        Variant::I16Ref(i) => Key::Num(Num::Int((**i).into())),
+        Variant::I32(i) => Key::Num(Num::Int((*i).into())),
+        Variant::I32Ref(i) => Key::Num(Num::Int((**i).into())),
+        Variant::I64(i) => Key::Num(Num::Int((*i).into())),
+        Variant::I64Ref(i) => Key::Num(Num::Int((**i).into())),
+        Variant::U8(i) => Key::Num(Num::Int((*i).into())),
+        Variant::U8Ref(i) => Key::Num(Num::Int((**i).into())),
+        Variant::U16(i) => Key::Num(Num::Int((*i).into())),
+        Variant::U16Ref(i) => Key::Num(Num::Int((**i).into())),
+        Variant::U32(i) => Key::Num(Num::Int((*i).into())),
+        Variant::U32Ref(i) => Key::Num(Num::Int((**i).into())),
+        Variant::U64(i) => Key::Num(Num::Int((*i).into())),
+        Variant::U64Ref(i) => Key::Num(Num::Int((**i).into())),
+        Variant::Int(i) => Key::Num(Num::Int((*i).into())),
+        Variant::IntRef(i) => Key::Num(Num::Int((**i).into())),
+        Variant::UInt(i) => Key::Num(Num::Int((*i).into())),
+        Variant::UIntRef(i) => Key::Num(Num::Int((**i).into())),
+        Variant::IntPtr(i) => Key::Num(Num::Int(*i as i128)),
+        Variant::UIntPtr(i) => Key::Num(Num::Int(*i as i128)),
+        Variant::F32(f) => Key::Num(Num::Float((*f).into())),
+        Variant::F32Ref(f) => Key::Num(Num::Float((**f).into())),
+        Variant::F64(f) => Key::Num(Num::Float(*f)),
+        Variant::F64Ref(f) => Key::Num(Num::Float(**f)),
+        Variant::Currency(c) => Key::Num(Num::Dec(c.0)),
+        Variant::CurrencyRef(c) => Key::Num(Num::Dec((**c).into())),
+        Variant::Decimal(d) => Key::Num(Num::Dec(*d)),
+        Variant::DecimalRef(d) => Key::Num(Num::Dec((&**d).try_into()?)),
+        Variant::Date(d) => Key::Num(Num::Float(days(ComDate::try_from(*d)?))),
+        Variant::DateRef(d) => Key::Num(Num::Float(days(**d))),
+        Variant::String(s) => Key::Str(s.as_wide().to_vec()),
+        Variant::StringRef(s) => Key::Str(s.as_wide().to_vec()),
+        Variant::VariantRef(v) => key(&Variant::try_from(&*v.0)?)?,
+        _ => {
+            return Err(VariantConversionError::TypeMismatch(
+                VariantType::VT_VARIANT,
+            ))
+        }
+    })
+}
+
+fn compare_numbers(a: Num, b: Num) -> Ordering {
+    match (a, b) {
+        (Num::Int(x), Num::Int(y)) => x.cmp(&y),
+        (Num::Float(_), _) | (_, Num::Float(_)) => to_float(a).total_cmp(&to_float(b)),
+        _ => {
+            let overflow = VariantType::VT_DECIMAL;
+            match (to_decimal(a, overflow), to_decimal(b, overflow)) {
+                (Ok(x), Ok(y)) => x.cmp(&y),
+                _ => to_float(a).total_cmp(&to_float(b)),
+            }
+        }
+    }
+}
+
+fn compare_strings(a: &[u16], b: &[u16], options: CompareOptions) -> Ordering {
+    if options.ignore_case {
+        let lower = |s| {
+            char::decode_utf16(s)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .flat_map(char::to_lowercase)
+        };
+        lower(a.iter().copied()).cmp(lower(b.iter().copied()))
+    } else {
+        a.cmp(b)
+    }
+}
+
+impl<'a> Variant<'a> {
+    /// Compares two values like `VarCmp`.
+    ///
+    /// Numeric types (including booleans and dates) are compared by value, so `I32(1)` equals
+    /// `F64(1.0)`. Dates are compared as the number of days since 30 December 1899, which is
+    /// their OLE value except that earlier times of day are always less. Strings are compared by UTF-16 code units, or case-insensitively depending on
+    /// `options`, and numbers are always less than strings. `Empty` is equal to both `0` and `""`.
+    /// If either value is `Null` the result is [`VariantOrdering::Null`], and other types such as
+    /// objects and arrays can't be compared.
+    pub fn compare(
+        &self,
+        other: &Variant,
+        options: CompareOptions,
+    ) -> Result<VariantOrdering, VariantConversionError> {
+        let ord = match (key(self)?, key(other)?) {
+            (Key::Null, _) | (_, Key::Null) => return Ok(VariantOrdering::Null),
+            (Key::Empty, Key::Empty) => Ordering::Equal,
+            (Key::Empty, Key::Num(n)) => compare_numbers(Num::Dec(Decimal::ZERO), n),
+            (Key::Num(n), Key::Empty) => compare_numbers(n, Num::Dec(Decimal::ZERO)),
+            (Key::Empty, Key::Str(s)) => compare_strings(&[], &s, options),
+            (Key::Str(s), Key::Empty) => compare_strings(&s, &[], options),
+            (Key::Num(a), Key::Num(b)) => compare_numbers(a, b),
+            (Key::Num(_), Key::Str(_)) => Ordering::Less,
+            (Key::Str(_), Key::Num(_)) => Ordering::Greater,
+            (Key::Str(a), Key::Str(b)) => compare_strings(&a, &b, options),
+        };
+        Ok(ord.into())
+    }
+}
+
+/// Orders values with [`Variant::compare`] and the default options. To stay consistent with `==`,
+/// which compares values structurally, values that are ordered as equal but differ, such as
+/// `I32(1)` and `F64(1.0)`, have no ordering, like values that are `Null` or can't be compared.
+/// Use [`Variant::compare`] to get the `VarCmp` result.
+impl<'a> PartialOrd for Variant<'a> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self
+            .compare(other, CompareOptions::default())
+            .ok()?
+            .to_ordering()?
+        {
+            Ordering::Equal if self != other => None,
+            ord => Some(ord),
+        }
+    }
+}
@@ -1,19 +1,26 @@
 //! Conversion between native [`VARIANT`] and Rust [`Variant`]
 
+use crate::coerce::copy_value;
 use crate::com_types::currency::ComCurrency;
 use crate::com_types::date::ComDate;
 use crate::com_types::decimal::ComDecimal;
+use crate::com_types::record::Record;
+use crate::com_types::safearray::{element_size, SafeArray, SafeArrayPtr};
+use crate::sys::{VARIANT, VARIANT_0_0_0_0};
 use crate::Variant::*;
 use crate::VariantType::*;
-use crate::{variant, ComBool, PtrWrapper, Variant, VariantType, VT_BYREF};
+use crate::{
+    variant, ComBool, OwnedVariant, PtrWrapper, VarType, Variant, VariantType, VT_ARRAY, VT_BYREF,
+};
+use chrono::NaiveDateTime;
 use std::string::FromUtf16Error;
 
 use std::convert::Infallible;
 use std::mem::ManuallyDrop;
 use thiserror::Error;
-use windows::core::HRESULT;
+use windows::core::{Error as WinError, HRESULT, PSTR};
 use windows::Win32::Foundation::VARIANT_BOOL;
-use windows::Win32::System::Variant::{VARENUM, VARIANT};
+use windows::Win32::System::Variant::VARENUM;
 
 #[derive(Debug, PartialEq, Eq, Error)]
 pub enum VariantConversionError {
@@ -21,16 +28,65 @@ pub enum VariantConversionError {
     StringConversionError,
     #[error("An unknown occured while converting the value of the Variant object.")]
     GenericConversionError,
-    #[error("The specified variant type is known but not supported.")]
+    #[error("The specified variant type is known but not supported: {0}.")]
     Unimplemented(VariantType),
-    #[error("A reference-only variant type was used without VT_BYREF.")]
-    InvalidDirect(VariantType),
-    #[error("An invalid variant type was used in conjunction with VT_BYREF.")]
-    InvalidReference(VariantType),
-    #[error("The specified type can only be used in a TYPEDESC structure.")]
-    TypeDescOnly(VariantType),
-    #[error("The specified variant type is unknown.")]
+    #[error("A reference-only variant type was used without VT_BYREF: {0}.")]
+    InvalidDirect(VarType),
+    #[error("An invalid variant type was used in conjunction with VT_BYREF: {0}.")]
+    InvalidReference(VarType),
+    #[error("The specified type can only be used in a TYPEDESC structure: {0}.")]
+    TypeDescOnly(VarType),
+    #[error("The specified type can only be used in a PROPVARIANT: {0}.")]
+    PropVariantOnly(VarType),
+    #[error("The specified variant type is unknown: {}.", type_name(*.0))]
     UnknownType(VARENUM),
+    #[error("The specified variant type name is unknown: {0:?}.")]
+    UnknownTypeName(std::string::String),
+    #[error("A by-reference variant can only be decoded from a borrowed VARIANT: {}.", type_name(*.0))]
+    BorrowedReference(VARENUM),
+    #[error("A by-reference variant holds a null pointer: {0}.")]
+    NullPointer(VarType),
+    #[error("A by-reference variant cannot be cloned, only dereferenced: {0}.")]
+    ReferenceClone(VarType),
+    #[error("The specified variant type cannot be used as an array element type: {0}.")]
+    InvalidArrayType(VariantType),
+    #[error("An array element does not match the array element type: {0}.")]
+    ArrayElementMismatch(VariantType),
+    #[error("The number of array elements does not match the array bounds.")]
+    ArrayBoundsMismatch,
+    #[error("The record has no IRecordInfo describing its type.")]
+    MissingRecordInfo,
+    #[error("A field is missing from the record.")]
+    MissingRecordField(std::string::String),
+    #[error("The value does not match any variant of the enum.")]
+    InvalidEnumValue(i32),
+    #[error("The DECIMAL has a scale above 28 or reserved sign bits set.")]
+    InvalidDecimal,
+    #[error("The VARIANT_BOOL is neither 0 nor -1: {0}.")]
+    InvalidBool(i16),
+    #[error("The value is out of range for the target type: {0}.")]
+    Overflow(VariantType),
+    #[error("The value cannot be converted to the target type: {0}.")]
+    TypeMismatch(VariantType),
+    #[error("The value cannot be represented in the target format: {0}.")]
+    Unrepresentable(VariantType),
+    #[error("Division by zero.")]
+    DivisionByZero,
+    #[error("A COM call failed while converting the value.")]
+    ComError(HRESULT),
+    #[error("A raw variant can only be converted back to a VARIANT: {}.", type_name(*.0))]
+    RawValue(VARENUM),
+}
+
+/// Builds an error holding the full type of a variant, or [`VariantConversionError::UnknownType`]
+/// if its base type is unknown
+fn type_error(vt: VARENUM, error: fn(VarType) -> VariantConversionError) -> VariantConversionError {
+    VarType::try_from(vt).map_or_else(|e| e, error)
+}
+
+/// Name of a raw variant type, or its hexadecimal value if the base type is unknown
+pub(crate) fn type_name(vt: VARENUM) -> std::string::String {
+    VarType::try_from(vt).map_or_else(|_| format!("{:#06x}", vt.0), |vt| vt.to_string())
 }
 
 impl From<Infallible> for VariantConversionError {
@@ -45,6 +101,12 @@ impl From<FromUtf16Error> for VariantConversionError {
     }
 }
 
+impl From<WinError> for VariantConversionError {
+    fn from(e: WinError) -> VariantConversionError {
+        VariantConversionError::ComError(e.code())
+    }
+}
+
 impl From<()> for VariantConversionError {
     fn from(_: ()) -> VariantConversionError {
         VariantConversionError::GenericConversionError
@@ -58,13 +120,13 @@ macro_rules! types
     // Direct-only type; custom expression
     // VT_DISPATCH : (Dispatch => (...), /),
     ( @vt $t: ident, $val:expr, $is_ref:expr, $vtype:ident => ($res:expr), / ) => {
-        if $is_ref { Err(VariantConversionError::InvalidReference($t)) }
+        if $is_ref { Err(type_error($val.Anonymous.vt, VariantConversionError::InvalidReference)) }
         else { $res.map(Variant::$vtype).map_err(Into::into) }
     };
 
     // VT_EMPTY : (Empty, /)
     ( @vt $t: ident, $val:expr, $is_ref:expr, $vtype:ident, / ) => {
-        if $is_ref { Err(VariantConversionError::InvalidReference($t)) }
+        if $is_ref { Err(type_error($val.Anonymous.vt, VariantConversionError::InvalidReference)) }
         else { Ok(Variant::$vtype) }
     };
 
@@ -72,11 +134,13 @@ macro_rules! types
     // VT_VARIANT : (/, VariantRef => ...)
     ( @vt $t: ident, $val:expr, $is_ref:expr, /, $atype: ident => ($ares: expr) ) => {
         if $is_ref { $ares.map(Variant::$atype).map_err(Into::into) }
-        else { Err(VariantConversionError::InvalidDirect($t)) }
+        else { Err(type_error($val.Anonymous.vt, VariantConversionError::InvalidDirect)) }
     };
 
     ( @ref $val: expr, $atype: ident, $ares: ident ) => {
-        ($val.Anonymous.Anonymous.$ares).as_mut::<'static>().ok_or(()).map(Into::into).map(Variant::$atype).map_err(Into::into)
+        ($val.Anonymous.Anonymous.$ares).as_mut()
+            .ok_or_else(|| type_error($val.Anonymous.vt, VariantConversionError::NullPointer))
+            .map(Into::into).map(Variant::$atype)
     };
 
     ( @ref $val: expr, $atype: ident, $ares: expr ) => {
@@ -102,83 +166,245 @@ macro_rules! types
             {
                 $($name => types!(@vt $name, $val, $is_ref, $($tts)*) ,)*
                 $($($pat => $expr ,)*)*
-                $($($u => Err(VariantConversionError::$err($u)),)*)*
+                $($($u => Err(type_error($val.Anonymous.vt, VariantConversionError::$err)),)*)*
             },
             None => Err(VariantConversionError::UnknownType($val.Anonymous.vt))
         }
     };
 }
 
-impl TryInto<Variant> for VARIANT {
+fn array_element_type(vt: VARENUM) -> Result<VariantType, VariantConversionError> {
+    VariantType::n(vt.0 & !(VT_ARRAY | VT_BYREF)).ok_or(VariantConversionError::UnknownType(vt))
+}
+
+/// Copies the fields of a `VT_RECORD` variant, then frees it
+unsafe fn take_record(var: VARIANT) -> Result<Record, VariantConversionError> {
+    let var = OwnedVariant::from_raw(var);
+    let rec = &var.as_raw().Anonymous.Anonymous.Anonymous.Anonymous;
+    Record::from_raw(rec.pvRecord, rec.pRecInfo.as_ref())
+}
+
+/// Decodes a [`VARIANT`], taking ownership of its value. By-reference values get an unbounded
+/// lifetime, so callers must bind it to something that outlives the referenced memory.
+unsafe fn from_raw<'a>(var: VARIANT) -> Result<Variant<'a>, VariantConversionError> {
+    let val = var.Anonymous;
+    let vt = val.Anonymous.vt;
+    let null = || type_error(vt, VariantConversionError::NullPointer);
+
+    if val.Anonymous.vt.0 & VT_ARRAY != 0 {
+        let element_type = array_element_type(val.Anonymous.vt)?;
+        return if val.Anonymous.vt.0 & VT_BYREF != 0 {
+            PtrWrapper::from_raw(val.Anonymous.Anonymous.pparray)
+                .map(|ptr| ArrayRef(SafeArrayPtr { element_type, ptr }))
+                .ok_or_else(null)
+        } else {
+            SafeArray::from_raw(val.Anonymous.Anonymous.parray, element_type).map(Array)
+        };
+    }
+
+    let (unrefd, is_ref) = if val.Anonymous.vt.0 & VT_BYREF != 0 {
+        (val.Anonymous.vt.0 & !VT_BYREF, true)
+    } else {
+        (val.Anonymous.vt.0, false)
+    };
+
+    types!(val, is_ref, VariantType::n(unrefd), [
+        VT_EMPTY : (Empty, /),
+        VT_NULL : (Null, /),
+
+        VT_BOOL : (Bool => (Ok(val.Anonymous.Anonymous.boolVal.0 != 0)), BoolRef => ((val.Anonymous.Anonymous.pboolVal as *mut ComBool).as_mut().ok_or_else(null))),
+
+        VT_I1 : (I8 => (Ok(val.Anonymous.Anonymous.cVal as i8)), I8Ref => ((val.Anonymous.Anonymous.pcVal.0 as *mut i8).as_mut().ok_or_else(null))),
+        VT_I2 : (I16 => iVal, I16Ref => piVal),
+        VT_I4 : (I32 => lVal, I32Ref => plVal),
+        VT_I8 : (I64 => llVal, I64Ref => pllVal),
+        VT_UI1 : (U8 => bVal, U8Ref => pbVal),
+        VT_UI2 : (U16 => uiVal, U16Ref => puiVal),
+        VT_UI4 : (U32 => ulVal, U32Ref => pulVal),
+        VT_UI8 : (U64 => ullVal, U64Ref => pullVal),
+        VT_INT : (Int => intVal, IntRef => pintVal),
+        VT_UINT : (UInt => uintVal, UIntRef => puintVal),
+        VT_INT_PTR : (IntPtr => (Ok::<_, Infallible>(val.Anonymous.Anonymous.llVal as isize)), /),
+        VT_UINT_PTR : (UIntPtr => (Ok::<_, Infallible>(val.Anonymous.Anonymous.ullVal as usize)), /),
+
+        VT_R4 : (F32 => fltVal, F32Ref => pfltVal),
+        VT_R8 : (F64 => dblVal, F64Ref => pdblVal),
+
+        VT_CY : (
+            Currency => (Ok(ComCurrency::from(val.Anonymous.Anonymous.cyVal).into())),
+            CurrencyRef => ((val.Anonymous.Anonymous.pcyVal as *mut ComCurrency).as_mut().ok_or_else(null))),
+
+        VT_DECIMAL : (
+            Decimal => (rust_decimal::Decimal::try_from(ComDecimal(val.decVal))),
+            DecimalRef => ((val.Anonymous.Anonymous.pdecVal as *mut ComDecimal).as_mut().ok_or_else(null))),
+
+        VT_DATE : (
+            Date => (NaiveDateTime::try_from(ComDate(val.Anonymous.Anonymous.date))),
+            DateRef => ((val.Anonymous.Anonymous.pdate as *mut ComDate).as_mut().ok_or_else(null))),
+
+        VT_BSTR : (
+            String => (Ok(ManuallyDrop::into_inner(ManuallyDrop::into_inner(val.Anonymous).Anonymous.bstrVal))),
+            StringRef => (val.Anonymous.Anonymous.pbstrVal.as_mut().ok_or_else(null))),
+
+        VT_DISPATCH : (
+            Dispatch => (Ok((*ManuallyDrop::into_inner(val.Anonymous).Anonymous.pdispVal).take())),
+            DispatchRef => (val.Anonymous.Anonymous.ppdispVal.as_mut().ok_or_else(null))),
+        VT_UNKNOWN : (
+            Unknown => (Ok((*ManuallyDrop::into_inner(val.Anonymous).Anonymous.punkVal).take())),
+            UnknownRef => (val.Anonymous.Anonymous.ppunkVal.as_mut().ok_or_else(null))),
+
+        VT_ERROR : (Error => (Ok(HRESULT(val.Anonymous.Anonymous.scode))), ErrorRef => ((val.Anonymous.Anonymous.pscode as *mut HRESULT).as_mut().ok_or_else(null))),
+
+        VT_VARIANT : (/, VariantRef => (PtrWrapper::from_raw(val.Anonymous.Anonymous.pvarVal).ok_or_else(null))),
+
+        VT_RECORD : (Record => (take_record(VARIANT { Anonymous: val })), /)
+    ], [
+
+    ], [
+        [
+            VT_VOID, VT_HRESULT,
+            VT_SAFEARRAY, VT_CARRAY,
+            VT_USERDEFINED,
+            VT_PTR
+        ] => TypeDescOnly,
+        [
+            VT_LPSTR, VT_LPWSTR,
+            VT_FILETIME, VT_BLOB,
+            VT_STREAM, VT_STORAGE,
+            VT_STREAMED_OBJECT, VT_STORED_OBJECT, VT_BLOB_OBJECT,
+            VT_CF, VT_CLSID, VT_VERSIONED_STREAM
+        ] => PropVariantOnly
+    ])
+}
+
+/// Handling of [`VARIANT`]s whose type isn't modelled by [`Variant`], such as unknown types or
+/// types that are only valid in a `TYPEDESC` or a `PROPVARIANT`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DecodeMode {
+    /// Fails with the corresponding error
+    #[default]
+    Strict,
+    /// Returns a [`Variant::Raw`] holding the original value, which is written back unchanged
+    /// when encoded
+    Lenient,
+}
+
+/// Whether a type can be decoded, whatever the value. This must match the types handled by
+/// [`from_raw`].
+fn is_supported(vt: VARENUM) -> bool {
+    let Some(base) = VariantType::n(vt.0 & !(VT_ARRAY | VT_BYREF)) else {
+        return false;
+    };
+    let is_ref = vt.0 & VT_BYREF != 0;
+    if vt.0 & VT_ARRAY != 0 {
+        // the element type of array references is only checked when they are read
+        return is_ref || element_size(base).is_ok();
+    }
+    match base {
+        VT_EMPTY | VT_NULL | VT_INT_PTR | VT_UINT_PTR | VT_RECORD => !is_ref,
+        VT_VARIANT => is_ref,
+        VT_BOOL | VT_I1 | VT_I2 | VT_I4 | VT_I8 | VT_UI1 | VT_UI2 | VT_UI4 | VT_UI8 | VT_INT
+        | VT_UINT | VT_R4 | VT_R8 | VT_CY | VT_DECIMAL | VT_DATE | VT_BSTR | VT_DISPATCH
+        | VT_UNKNOWN | VT_ERROR => true,
+        _ => false,
+    }
+}
+
+impl Variant<'static> {
+    /// Decodes an owned [`VARIANT`] like [`TryInto`], keeping values of unsupported types,
+    /// including by-reference ones, as [`Variant::Raw`] with [`DecodeMode::Lenient`]
+    pub fn decode(var: VARIANT, mode: DecodeMode) -> Result<Self, VariantConversionError> {
+        let vt = unsafe { var.Anonymous.Anonymous.vt };
+        if mode == DecodeMode::Lenient && !is_supported(vt) {
+            return Ok(Raw(unsafe { OwnedVariant::from_raw(var) }));
+        }
+        var.try_into()
+    }
+}
+
+impl<'a> Variant<'a> {
+    /// Decodes a borrowed [`VARIANT`] like [`TryFrom`], moving values of unsupported types,
+    /// including by-reference ones, into a [`Variant::Raw`] with [`DecodeMode::Lenient`]
+    pub fn decode_mut(
+        var: &'a mut VARIANT,
+        mode: DecodeMode,
+    ) -> Result<Self, VariantConversionError> {
+        if mode == DecodeMode::Lenient && !is_supported(unsafe { var.Anonymous.Anonymous.vt }) {
+            return Ok(Raw(unsafe { OwnedVariant::from_raw(std::mem::take(var)) }));
+        }
+        var.try_into()
+    }
+}
+
+impl TryInto<Variant<'static>> for VARIANT {
     type Error = VariantConversionError;
 
-    fn try_into(self) -> Result<Variant, VariantConversionError> {
+    /// Decodes an owned [`VARIANT`]. By-reference values don't own the memory they point to, so
+    /// they can only be decoded from a borrowed [`VARIANT`], and fail with
+    /// [`VariantConversionError::BorrowedReference`]. [`Variant::deref_clone`] then gives an owned
+    /// copy of the referenced value.
+    fn try_into(self) -> Result<Variant<'static>, VariantConversionError> {
         unsafe {
-            let val = self.Anonymous;
+            if self.Anonymous.Anonymous.vt.0 & VT_BYREF != 0 {
+                return Err(VariantConversionError::BorrowedReference(
+                    self.Anonymous.Anonymous.vt,
+                ));
+            }
+            from_raw(self)
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a mut VARIANT> for Variant<'a> {
+    type Error = VariantConversionError;
 
-            let (unrefd, is_ref) = if val.Anonymous.vt.0 & VT_BYREF != 0 {
-                (val.Anonymous.vt.0 & !VT_BYREF, true)
+    /// Decodes a borrowed [`VARIANT`]. By-reference values borrow the memory they point to for as
+    /// long as the [`VARIANT`] is borrowed. Other values are moved out of the [`VARIANT`], which is
+    /// left as `VT_EMPTY`.
+    fn try_from(var: &'a mut VARIANT) -> Result<Variant<'a>, VariantConversionError> {
+        unsafe {
+            if var.Anonymous.Anonymous.vt.0 & VT_BYREF != 0 {
+                from_raw(std::ptr::read(var))
             } else {
-                (val.Anonymous.vt.0, false)
-            };
-
-            types!(val, is_ref, VariantType::n(unrefd), [
-                VT_EMPTY : (Empty, /),
-                VT_NULL : (Null, /),
-
-                VT_BOOL : (Bool => (Ok(val.Anonymous.Anonymous.boolVal.0 != 0)), BoolRef => (Ok(&mut *(val.Anonymous.Anonymous.pboolVal as *mut ComBool)))),
-
-                VT_I1 : (I8 => (Ok(val.Anonymous.Anonymous.cVal as i8)), I8Ref => (Ok(val.Anonymous.Anonymous.pcVal))),
-                VT_I2 : (I16 => iVal, I16Ref => piVal),
-                VT_I4 : (I32 => lVal, I32Ref => plVal),
-                VT_I8 : (I64 => llVal, I64Ref => pllVal),
-                VT_UI1 : (U8 => bVal, U8Ref => pbVal),
-                VT_UI2 : (U16 => uiVal, U16Ref => puiVal),
-                VT_UI4 : (U32 => ulVal, U32Ref => pulVal),
-                VT_UI8 : (U64 => ullVal, U64Ref => pullVal),
-                VT_INT : (I32 => lVal, I32Ref => plVal),
-                VT_UINT : (U32 => ulVal, U32Ref => pulVal),
-
-                VT_R4 : (F32 => fltVal, F32Ref => pfltVal),
-                VT_R8 : (F64 => dblVal, F64Ref => pdblVal),
-
-                VT_CY : (
-                    Currency => (Ok(ComCurrency::from(val.Anonymous.Anonymous.cyVal).into())),
-                    CurrencyRef => (Ok(<&mut ComCurrency>::from(val.Anonymous.Anonymous.pcyVal)))),
-
-                VT_DECIMAL : (
-                    Decimal => (Ok(ComDecimal(val.decVal).into())),
-                    DecimalRef => (Ok(<&mut ComDecimal>::from(val.Anonymous.Anonymous.pdecVal)))),
-
-                VT_DATE : (
-                    Date => (Ok(ComDate(val.Anonymous.Anonymous.date).into())),
-                    DateRef => (Ok(<&mut ComDate>::from(val.Anonymous.Anonymous.pdate)))),
-
-                VT_BSTR : (String => (Ok::<_, Infallible>(ManuallyDrop::into_inner(ManuallyDrop::into_inner(val.Anonymous).Anonymous.bstrVal))), /),
-
-                VT_DISPATCH : (Dispatch => (Ok::<_, Infallible>((*ManuallyDrop::into_inner(val.Anonymous).Anonymous.pdispVal).take())), /),
-                VT_UNKNOWN : (Unknown => (Ok::<_, Infallible>((*ManuallyDrop::into_inner(val.Anonymous).Anonymous.punkVal).take())), /),
-
-                VT_ERROR : (Error => (Ok(HRESULT(val.Anonymous.Anonymous.scode))), ErrorRef => (Ok((val.Anonymous.Anonymous.pscode as *mut HRESULT).as_mut::<'static>().unwrap()))),
-
-                VT_VARIANT : (/, VariantRef => (PtrWrapper::try_from(&val.Anonymous.Anonymous.pvarVal)))
-            ], [
-
-            ], [
-                [VT_RECORD] => Unimplemented,
-                [
-                    VT_VOID, VT_HRESULT,
-                    VT_SAFEARRAY, VT_CARRAY,
-                    VT_USERDEFINED,
-                    VT_LPSTR, VT_LPWSTR,
-                    VT_PTR, VT_INT_PTR, VT_UINT_PTR
-                ] => TypeDescOnly
-            ])
+                from_raw(std::mem::take(var))
+            }
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a VARIANT> for Variant<'static> {
+    type Error = VariantConversionError;
+
+    /// Decodes a [`VARIANT`] without taking ownership of its value, like `VariantCopyInd`: strings
+    /// are duplicated, interfaces are `AddRef`'d, and arrays and records are copied. By-reference
+    /// values are copied from the memory they point to, so `VT_I4 | VT_BYREF` gives `I32`.
+    fn try_from(var: &'a VARIANT) -> Result<Variant<'static>, VariantConversionError> {
+        unsafe {
+            let vt = var.Anonymous.Anonymous.vt;
+            if vt.0 & VT_BYREF != 0 {
+                // by-reference variants don't own what they point to, so reading them takes nothing
+                return copy_value(&from_raw(std::ptr::read(var))?);
+            }
+            let val = &var.Anonymous.Anonymous.Anonymous;
+            if vt.0 & VT_ARRAY != 0 {
+                return SafeArray::copy_from_raw(val.parray, array_element_type(vt)?).map(Array);
+            }
+            match VariantType::n(vt.0) {
+                Some(VT_BSTR) => Ok(String((*val.bstrVal).clone())),
+                Some(VT_DISPATCH) => Ok(Dispatch((*val.pdispVal).clone())),
+                Some(VT_UNKNOWN) => Ok(Unknown((*val.punkVal).clone())),
+                Some(VT_RECORD) => {
+                    Record::from_raw(val.Anonymous.pvRecord, val.Anonymous.pRecInfo.as_ref())
+                        .map(Record)
+                }
+                // the remaining types don't own any memory
+                _ => from_raw(std::ptr::read(var)),
+            }
         }
     }
 }
 
-impl TryInto<VARIANT> for Variant {
+impl<'a> TryInto<VARIANT> for Variant<'a> {
     type Error = VariantConversionError;
 
     fn try_into(self) -> Result<VARIANT, VariantConversionError> {
@@ -186,19 +412,19 @@ impl TryInto<VARIANT> for Variant {
             Empty => Ok(variant!(VT_EMPTY)),
             Null => Ok(variant!(VT_NULL)),
 
-            Bool(b) => Ok(variant!(
-                VT_BOOL,
-                boolVal,
-                VARIANT_BOOL(ComBool::from(b) as i16)
-            )),
+            Bool(b) => Ok(variant!(VT_BOOL, boolVal, ComBool::from(b).into())),
             BoolRef(b) => Ok(variant!(
-                VT_BOOL,
+                VT_BOOL.byref(),
                 pboolVal,
                 b as *mut ComBool as *mut VARIANT_BOOL
             )),
 
             I8(i) => Ok(variant!(VT_I1, cVal, i as u8)),
-            I8Ref(i) => Ok(variant!(VT_I1.byref(), pcVal, i)),
+            I8Ref(i) => Ok(variant!(
+                VT_I1.byref(),
+                pcVal,
+                PSTR(i as *mut i8 as *mut u8)
+            )),
             I16(i) => Ok(variant!(VT_I2, iVal, i)),
             I16Ref(i) => Ok(variant!(VT_I2.byref(), piVal, i)),
             I32(i) => Ok(variant!(VT_I4, lVal, i)),
@@ -215,31 +441,65 @@ impl TryInto<VARIANT> for Variant {
             U64(i) => Ok(variant!(VT_UI8, ullVal, i)),
             U64Ref(i) => Ok(variant!(VT_UI8.byref(), pullVal, i)),
 
+            Int(i) => Ok(variant!(VT_INT, intVal, i)),
+            IntRef(i) => Ok(variant!(VT_INT.byref(), pintVal, i)),
+            UInt(i) => Ok(variant!(VT_UINT, uintVal, i)),
+            UIntRef(i) => Ok(variant!(VT_UINT.byref(), puintVal, i)),
+            // pointer-sized integers use the low bytes of the 64-bit field on 32-bit targets
+            IntPtr(i) => Ok(variant!(VT_INT_PTR, llVal, i as i64)),
+            UIntPtr(i) => Ok(variant!(VT_UINT_PTR, ullVal, i as u64)),
+
             F32(f) => Ok(variant!(VT_R4, fltVal, f)),
             F32Ref(f) => Ok(variant!(VT_R4.byref(), pfltVal, f)),
             F64(f) => Ok(variant!(VT_R8, dblVal, f)),
             F64Ref(f) => Ok(variant!(VT_R8.byref(), pdblVal, f)),
 
-            Currency(d) => Ok(variant!(VT_CY, cyVal, ComCurrency::from(d).into())),
+            Currency(d) => Ok(variant!(VT_CY, cyVal, ComCurrency::try_from(d)?.into())),
             CurrencyRef(r) => Ok(variant!(VT_CY.byref(), pcyVal, r.as_mut_ptr())),
 
             Decimal(d) => Ok(variant!(VT_DECIMAL, (decVal), ComDecimal::from(d).0)),
             DecimalRef(d) => Ok(variant!(VT_DECIMAL.byref(), pdecVal, d.as_mut_ptr())),
 
-            Date(d) => Ok(variant!(VT_DATE, date, ComDate::from(d).0)),
+            Date(d) => Ok(variant!(VT_DATE, date, ComDate::try_from(d)?.0)),
             DateRef(d) => Ok(variant!(VT_DATE.byref(), pdate, d.as_mut_ptr())),
 
             String(s) => Ok(variant!(VT_BSTR, bstrVal, ManuallyDrop::new(s))),
             StringRef(s) => Ok(variant!(VT_BSTR.byref(), pbstrVal, s)),
 
             Dispatch(ptr) => Ok(variant!(VT_DISPATCH, pdispVal, ManuallyDrop::new(ptr))),
+            DispatchRef(ptr) => Ok(variant!(VT_DISPATCH.byref(), ppdispVal, ptr)),
             Unknown(ptr) => Ok(variant!(VT_UNKNOWN, punkVal, ManuallyDrop::new(ptr))),
+            UnknownRef(ptr) => Ok(variant!(VT_UNKNOWN.byref(), ppunkVal, ptr)),
 
             Error(code) => Ok(variant!(VT_ERROR, scode, code.0)),
             ErrorRef(code) => Ok(variant!(VT_ERROR.byref(), pscode, &mut code.0)),
 
             VariantRef(ptr) => Ok(variant!(VT_VARIANT.byref(), pvarVal, ptr.0)),
-            //_ => Err(VariantConversionError::GenericConversionError),
+
+            Array(arr) => Ok(variant!(
+                arr.element_type().array(),
+                parray,
+                arr.into_raw()?
+            )),
+            ArrayRef(arr) => Ok(variant!(
+                arr.element_type.array() | VT_BYREF,
+                pparray,
+                arr.ptr.0
+            )),
+
+            Record(rec) => {
+                let (data, info) = rec.into_raw()?;
+                Ok(variant!(
+                    VT_RECORD,
+                    Anonymous,
+                    ManuallyDrop::new(VARIANT_0_0_0_0 {
+                        pvRecord: data,
+                        pRecInfo: ManuallyDrop::new(Some(info)),
+                    })
+                ))
+            }
+
+            Raw(var) => Ok(var.into_raw()),
         }
     }
 }
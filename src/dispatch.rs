@@ -1,6 +1,8 @@
 //! Utilities for using [`IDispatch`] from Rust in an ergonomic fashion
 
 use crate::convert::VariantConversionError;
+use crate::owned::OwnedVariant;
+use crate::sys::VARIANT;
 use crate::variant::Variant;
 use thiserror::Error;
 use widestring::U16CString;
@@ -11,12 +13,11 @@ use windows::Win32::System::Com::{
     DISPPARAMS, EXCEPINFO,
 };
 use windows::Win32::System::Ole::DISPID_PROPERTYPUT;
-use windows::Win32::System::Variant::VARIANT;
 
 pub trait IDispatchExt {
-    fn get(&self, name: &str) -> Result<Variant, IDispatchError>;
-    fn put(&self, name: &str, value: Variant) -> Result<(), IDispatchError>;
-    fn call(&self, name: &str, args: Vec<Variant>) -> Result<Variant, IDispatchError>;
+    fn get(&self, name: &str) -> Result<Variant<'static>, IDispatchError>;
+    fn put(&self, name: &str, value: Variant<'_>) -> Result<(), IDispatchError>;
+    fn call(&self, name: &str, args: Vec<Variant<'_>>) -> Result<Variant<'static>, IDispatchError>;
 }
 
 #[derive(Error, Debug)]
@@ -49,7 +50,7 @@ fn invoke(
     name: &str,
     dp: &mut DISPPARAMS,
     flags: DISPATCH_FLAGS,
-) -> Result<Variant, IDispatchError> {
+) -> Result<Variant<'static>, IDispatchError> {
     let mut name = U16CString::from_str(name).map_err(IDispatchError::StringConversion)?;
     let mut id = 0i32;
     unsafe {
@@ -65,7 +66,7 @@ fn invoke(
 
     let mut excep = EXCEPINFO::default();
     let mut arg_err = 0;
-    let mut result = VARIANT::default();
+    let mut result = OwnedVariant::new();
 
     let res = unsafe {
         obj.Invoke(
@@ -74,7 +75,7 @@ fn invoke(
             LOCALE_SYSTEM_DEFAULT,
             flags,
             dp,
-            Some(&mut result),
+            Some(result.as_mut_ptr()),
             Some(&mut excep),
             Some(&mut arg_err),
         )
@@ -98,14 +99,18 @@ fn invoke(
 /// Get a property from the COM object
 ///
 /// # Example
-/// ```
+/// ```no_run
+/// # use windows::Win32::System::Com::IDispatch;
+/// # fn example(com_object: IDispatch) -> Result<(), variant_rs::dispatch::IDispatchError> {
 /// use variant_rs::get;
 /// let x = get!(com_object, SomeProp)?;
+/// # Ok(())
+/// # }
 /// ```
 #[macro_export]
 macro_rules! get {
     ($obj:expr, $name:ident) => {{
-        use variant_rs::dispatch::IDispatchExt;
+        use $crate::dispatch::IDispatchExt;
         $obj.get(stringify!($name))
     }};
 }
@@ -113,15 +118,19 @@ macro_rules! get {
 /// Set a property on the COM object
 ///
 /// # Example
-/// ```
+/// ```no_run
+/// # use windows::Win32::System::Com::IDispatch;
+/// # fn example(com_object: IDispatch) -> Result<(), variant_rs::dispatch::IDispatchError> {
 /// use variant_rs::put;
 /// put!(com_object, SomeProp, 10)?;
+/// # Ok(())
+/// # }
 /// ```
 #[macro_export]
 macro_rules! put {
     ($obj:expr, $name:ident, $value:expr) => {{
-        use variant_rs::dispatch::IDispatchExt;
-        let val: Variant = $value.into();
+        use $crate::dispatch::IDispatchExt;
+        let val: $crate::Variant = $value.into();
         $obj.put(stringify!($name), val)
     }};
 }
@@ -129,15 +138,19 @@ macro_rules! put {
 /// Call a method on the COM object
 ///
 /// # Example
-/// ```
+/// ```no_run
+/// # use windows::Win32::System::Com::IDispatch;
+/// # fn example(com_object: IDispatch) -> Result<(), variant_rs::dispatch::IDispatchError> {
 /// use variant_rs::call;
 /// let x = call!(com_object, SomeMethod(10, "hello"))?;
+/// # Ok(())
+/// # }
 /// ```
 #[macro_export]
 macro_rules! call {
     ($obj:expr, $name:ident($($arg:expr),*)) => {
         {
-            use variant_rs::dispatch::IDispatchExt;
+            use $crate::dispatch::IDispatchExt;
             let args = vec![$((&$arg).into()),*];
             $obj.call(stringify!($name), args)
         }
@@ -148,7 +161,7 @@ impl IDispatchExt for IDispatch {
     /// Get a property from a COM object
     ///
     /// Note: consider using the [`get!`] macro
-    fn get(&self, name: &str) -> Result<Variant, IDispatchError> {
+    fn get(&self, name: &str) -> Result<Variant<'static>, IDispatchError> {
         let mut dp = DISPPARAMS::default();
         invoke(self, name, &mut dp, DISPATCH_PROPERTYGET)
     }
@@ -156,11 +169,11 @@ impl IDispatchExt for IDispatch {
     /// Set a property on a COM object
     ///
     /// Note: consider using the [`put!`] macro
-    fn put(&self, name: &str, value: Variant) -> Result<(), IDispatchError> {
-        let mut value = value.try_into()?;
+    fn put(&self, name: &str, value: Variant<'_>) -> Result<(), IDispatchError> {
+        let mut value = OwnedVariant::try_from(value)?;
         let mut dp = DISPPARAMS {
             cArgs: 1,
-            rgvarg: &mut value,
+            rgvarg: value.as_mut_ptr(),
             cNamedArgs: 1,
             ..Default::default()
         };
@@ -173,15 +186,16 @@ impl IDispatchExt for IDispatch {
     /// Call a method on a COM object
     ///
     /// Note: consider using the [`call!`] macro
-    fn call(&self, name: &str, args: Vec<Variant>) -> Result<Variant, IDispatchError> {
+    fn call(&self, name: &str, args: Vec<Variant<'_>>) -> Result<Variant<'static>, IDispatchError> {
         let mut dp = DISPPARAMS::default();
-        let args: Vec<VARIANT> = args
+        let mut args: Vec<OwnedVariant> = args
             .into_iter()
             .rev()
             .map(|v| v.try_into().map_err(IDispatchError::from))
             .collect::<Result<_, _>>()?;
         dp.cArgs = args.len() as u32;
-        dp.rgvarg = args.as_ptr() as *mut _;
+        // OwnedVariant is a transparent wrapper around VARIANT
+        dp.rgvarg = args.as_mut_ptr() as *mut VARIANT;
         invoke(self, name, &mut dp, DISPATCH_METHOD)
     }
 }
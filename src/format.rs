@@ -0,0 +1,180 @@
+//! Conversion of [`Variant`] values to text, following the rules of `CStr` in VB
+
+use crate::coerce::copy_value;
+use crate::convert::VariantConversionError;
+use crate::{Variant, VariantType};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use rust_decimal::Decimal;
+use std::fmt::Display;
+
+/// Order of the day, month and year in formatted dates
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DateOrder {
+    #[default]
+    MonthDayYear,
+    DayMonthYear,
+    YearMonthDay,
+}
+
+/// How `Null` is formatted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum NullMode {
+    /// `Null` is formatted as `"Null"`, like VB does when printing it
+    #[default]
+    Text,
+    /// `Null` fails with [`VariantConversionError::TypeMismatch`], like `CStr(Null)` does
+    Error,
+}
+
+/// Locale settings used by [`Variant::format`]. The default settings are those of US English.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DisplaySettings {
+    pub decimal_separator: char,
+    pub date_separator: char,
+    pub date_order: DateOrder,
+    /// Formats times as `14:30:00` rather than `2:30:00 PM`
+    pub hour24: bool,
+    pub null_mode: NullMode,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            decimal_separator: '.',
+            date_separator: '/',
+            date_order: DateOrder::default(),
+            hour24: false,
+            null_mode: NullMode::default(),
+        }
+    }
+}
+
+impl<'a> Variant<'a> {
+    /// Formats the value like `CStr`, using the given locale settings.
+    ///
+    /// Booleans are formatted as `True` and `False`, floats with the shortest representation that
+    /// round-trips (switching to scientific notation like `1E+15` for large and small values),
+    /// currencies and decimals without trailing zeros, and dates like `3/1/2024 2:30:00 PM`.
+    /// `Empty` gives an empty string. Objects, arrays and errors can't be formatted.
+    pub fn format(&self, settings: &DisplaySettings) -> Result<String, VariantConversionError> {
+        format_value(copy_value(self)?, settings)
+    }
+}
+
+/// Formats values with [`Variant::format`] and the default settings. Values that can't be
+/// formatted are written with their [`Debug`](std::fmt::Debug) representation instead.
+impl<'a> Display for Variant<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.format(&DisplaySettings::default()) {
+            Ok(s) => f.pad(&s),
+            Err(_) => write!(f, "{:?}", self),
+        }
+    }
+}
+
+pub(crate) fn format_value(
+    value: Variant<'static>,
+    settings: &DisplaySettings,
+) -> Result<String, VariantConversionError> {
+    let sep = settings.decimal_separator;
+    Ok(match value {
+        Variant::Empty => String::new(),
+        Variant::Null if settings.null_mode == NullMode::Text => "Null".to_string(),
+        Variant::Bool(b) => (if b { "True" } else { "False" }).to_string(),
+        Variant::I8(i) => i.to_string(),
+        Variant::I16(i) => i.to_string(),
+        Variant::I32(i) => i.to_string(),
+        Variant::I64(i) => i.to_string(),
+        Variant::U8(i) => i.to_string(),
+        Variant::U16(i) => i.to_string(),
+        Variant::U32(i) => i.to_string(),
+        Variant::U64(i) => i.to_string(),
+        Variant::Int(i) => i.to_string(),
+        Variant::UInt(i) => i.to_string(),
+        Variant::IntPtr(i) => i.to_string(),
+        Variant::UIntPtr(i) => i.to_string(),
+        Variant::F32(f) => format_float(&format!("{:e}", f), 7, sep),
+        Variant::F64(f) => format_float(&format!("{:e}", f), 15, sep),
+        Variant::Currency(c) => format_decimal(c.0, sep),
+        Variant::Decimal(d) => format_decimal(d, sep),
+        Variant::Date(d) => format_date(d, settings),
+        Variant::String(s) => s.to_string(),
+        _ => return Err(VariantConversionError::TypeMismatch(VariantType::VT_BSTR)),
+    })
+}
+
+fn format_decimal(d: Decimal, decimal_separator: char) -> String {
+    d.normalize()
+        .to_string()
+        .replace('.', &decimal_separator.to_string())
+}
+
+/// Formats a float from its Rust scientific representation (such as `1.25e-3`), switching to
+/// scientific notation like `%G` does for exponents below -4 or from `max_exponent` upwards
+/// (e.g. `1E-05` and `1E+15`).
+pub(crate) fn format_float(scientific: &str, max_exponent: i32, decimal_separator: char) -> String {
+    let Some((mantissa, exponent)) = scientific.split_once('e') else {
+        // infinity and NaN
+        return scientific.to_string();
+    };
+    let exponent: i32 = exponent.parse().unwrap();
+    let sign = if mantissa.starts_with('-') { "-" } else { "" };
+    let digits: String = mantissa.chars().filter(|c| c.is_ascii_digit()).collect();
+    let digits = digits.trim_end_matches('0');
+    if digits.is_empty() {
+        return "0".to_string();
+    }
+
+    if exponent < -4 || exponent >= max_exponent {
+        let (first, rest) = digits.split_at(1);
+        let dot = if rest.is_empty() {
+            String::new()
+        } else {
+            decimal_separator.to_string()
+        };
+        let exp_sign = if exponent < 0 { '-' } else { '+' };
+        format!("{sign}{first}{dot}{rest}E{exp_sign}{:02}", exponent.abs())
+    } else if exponent < 0 {
+        let zeros = "0".repeat((-exponent - 1) as usize);
+        format!("{sign}0{decimal_separator}{zeros}{digits}")
+    } else {
+        let int_len = exponent as usize + 1;
+        if digits.len() <= int_len {
+            format!("{sign}{digits:0<int_len$}")
+        } else {
+            let (int, frac) = digits.split_at(int_len);
+            format!("{sign}{int}{decimal_separator}{frac}")
+        }
+    }
+}
+
+/// Formats a date in the short date format. Midnight is omitted, and so is the date for times on
+/// 30 December 1899 (day zero).
+fn format_date(date: NaiveDateTime, settings: &DisplaySettings) -> String {
+    let s = settings.date_separator;
+    let (y, m, d) = (date.year(), date.month(), date.day());
+    let day = match settings.date_order {
+        DateOrder::MonthDayYear => format!("{m}{s}{d}{s}{y}"),
+        DateOrder::DayMonthYear => format!("{d}{s}{m}{s}{y}"),
+        DateOrder::YearMonthDay => format!("{y}{s}{m}{s}{d}"),
+    };
+    let time = if settings.hour24 {
+        format!("{}:{:02}:{:02}", date.hour(), date.minute(), date.second())
+    } else {
+        let (pm, hour) = date.time().hour12();
+        format!(
+            "{}:{:02}:{:02} {}",
+            hour,
+            date.minute(),
+            date.second(),
+            if pm { "PM" } else { "AM" }
+        )
+    };
+    if date.date() == NaiveDate::from_ymd_opt(1899, 12, 30).unwrap() {
+        time
+    } else if date.time() == NaiveTime::MIN {
+        day
+    } else {
+        format!("{} {}", day, time)
+    }
+}
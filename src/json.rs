@@ -0,0 +1,156 @@
+//! Conversion between [`Variant`] and [`serde_json::Value`], enabled by the `json` feature
+
+use crate::coerce::{copy_value, natural_type};
+use crate::com_types::record::{Record, RecordType};
+use crate::com_types::safearray::{SafeArray, SafeArrayBound};
+use crate::convert::VariantConversionError;
+use crate::sys::BSTR;
+use crate::{Variant, VariantType};
+use serde_json::{Map, Number, Value};
+use windows::core::GUID;
+
+/// Handling of values that have no JSON equivalent, such as objects, error codes and infinite
+/// floats
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum JsonPolicy {
+    /// Fails with [`VariantConversionError::Unrepresentable`]
+    #[default]
+    Error,
+    /// Replaces the value with `null`
+    Null,
+    /// Replaces the value with a string holding its [`Debug`](std::fmt::Debug) representation
+    Debug,
+}
+
+impl<'a> Variant<'a> {
+    /// Converts the value to JSON.
+    ///
+    /// Numbers, strings and booleans are mapped to their JSON equivalent, and `Null`, `Empty` and
+    /// null interface pointers to `null`. Currencies and decimals are converted to strings to
+    /// keep their exact value, and dates to ISO 8601 strings. Arrays become JSON arrays, nested
+    /// for multi-dimensional arrays, and records become objects whose keys are in the order of
+    /// the fields (the last one wins if several fields have the same name). Other values are
+    /// handled according to `policy`. By-reference values are dereferenced.
+    pub fn to_json(&self, policy: JsonPolicy) -> Result<Value, VariantConversionError> {
+        to_json(copy_value(self)?, policy)
+    }
+
+    /// Converts a JSON value to a variant.
+    ///
+    /// `null` gives `Null`, integers give `I32` when they fit and `I64` or `U64` otherwise, and
+    /// other numbers give `F64`. Strings are not parsed. Arrays give one-dimensional arrays of
+    /// `VT_VARIANT`, and objects give records with an anonymous type.
+    pub fn from_json(value: &Value) -> Variant<'static> {
+        match value {
+            Value::Null => Variant::Null,
+            Value::Bool(b) => Variant::Bool(*b),
+            Value::Number(n) => {
+                if let Some(i) = n.as_i64() {
+                    i32::try_from(i).map_or(Variant::I64(i), Variant::I32)
+                } else if let Some(u) = n.as_u64() {
+                    Variant::U64(u)
+                } else {
+                    Variant::F64(n.as_f64().unwrap_or(f64::NAN))
+                }
+            }
+            Value::String(s) => Variant::String(BSTR::from(s.as_str())),
+            Value::Array(a) => Variant::Array(SafeArray::from_vec(
+                a.iter().map(Variant::from_json).collect(),
+            )),
+            Value::Object(o) => Variant::Record(Record::new(
+                RecordType::new("", GUID::zeroed()),
+                o.iter()
+                    .map(|(k, v)| (k.clone(), Variant::from_json(v)))
+                    .collect(),
+            )),
+        }
+    }
+}
+
+fn unrepresentable(
+    value: &Variant<'static>,
+    policy: JsonPolicy,
+) -> Result<Value, VariantConversionError> {
+    match policy {
+        JsonPolicy::Error => Err(VariantConversionError::Unrepresentable(
+            natural_type(value).unwrap_or(VariantType::VT_VARIANT),
+        )),
+        JsonPolicy::Null => Ok(Value::Null),
+        JsonPolicy::Debug => Ok(Value::String(format!("{:?}", value))),
+    }
+}
+
+fn float(
+    value: &Variant<'static>,
+    f: f64,
+    policy: JsonPolicy,
+) -> Result<Value, VariantConversionError> {
+    match Number::from_f64(f) {
+        Some(n) => Ok(Value::Number(n)),
+        None => unrepresentable(value, policy),
+    }
+}
+
+fn to_json(value: Variant<'static>, policy: JsonPolicy) -> Result<Value, VariantConversionError> {
+    Ok(match value {
+        Variant::Empty | Variant::Null | Variant::Dispatch(None) | Variant::Unknown(None) => {
+            Value::Null
+        }
+        Variant::Bool(b) => Value::Bool(b),
+        Variant::I8(i) => i.into(),
+        Variant::I16(i) => i.into(),
+        Variant::I32(i) => i.into(),
+        Variant::I64(i) => i.into(),
+        Variant::U8(i) => i.into(),
+        Variant::U16(i) => i.into(),
+        Variant::U32(i) => i.into(),
+        Variant::U64(i) => i.into(),
+        Variant::Int(i) => i.into(),
+        Variant::UInt(i) => i.into(),
+        Variant::IntPtr(i) => i.into(),
+        Variant::UIntPtr(i) => i.into(),
+        Variant::F32(f) => return float(&value, f.into(), policy),
+        Variant::F64(f) => return float(&value, f, policy),
+        Variant::Currency(c) => Value::String(c.0.to_string()),
+        Variant::Decimal(d) => Value::String(d.to_string()),
+        Variant::Date(d) => Value::String(d.format("%Y-%m-%dT%H:%M:%S%.f").to_string()),
+        Variant::String(s) => Value::String(s.to_string()),
+        Variant::Array(a) => {
+            if a.bounds().is_empty() {
+                Value::Null
+            } else {
+                let bounds = a.bounds().to_vec();
+                let mut elements = a.into_elements().into_iter().map(Some).collect::<Vec<_>>();
+                array_to_json(&mut elements, &bounds, 0, 1, policy)?
+            }
+        }
+        Variant::Record(r) => Value::Object(
+            r.fields
+                .into_iter()
+                .map(|(k, v)| Ok((k, to_json(v, policy)?)))
+                .collect::<Result<Map<_, _>, VariantConversionError>>()?,
+        ),
+        _ => return unrepresentable(&value, policy),
+    })
+}
+
+/// Converts the sub-array over the given trailing dimensions, the first one being the outermost,
+/// taking its elements out of the array. `offset` is the position of its first element, and
+/// `stride` the distance between two of its elements along its first dimension.
+fn array_to_json(
+    elements: &mut [Option<Variant<'static>>],
+    bounds: &[SafeArrayBound],
+    offset: usize,
+    stride: usize,
+    policy: JsonPolicy,
+) -> Result<Value, VariantConversionError> {
+    let Some((bound, rest)) = bounds.split_first() else {
+        let element = elements[offset].take().unwrap_or(Variant::Empty);
+        return to_json(element, policy);
+    };
+    let len = bound.len as usize;
+    (0..len)
+        .map(|i| array_to_json(elements, rest, offset + i * stride, stride * len, policy))
+        .collect::<Result<_, _>>()
+        .map(Value::Array)
+}
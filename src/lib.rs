@@ -3,13 +3,35 @@
 
 use crate::com_types::bool::ComBool;
 use crate::com_types::ptr_wrapper::PtrWrapper;
+pub use crate::owned::OwnedVariant;
+#[cfg(not(all(feature = "portable", not(windows))))]
+pub use crate::propvariant::PropVariant;
+pub use crate::sys::{BSTR, VARIANT};
 pub use crate::variant::*;
 
-pub use windows::Win32::System::Variant::{VARENUM, VARIANT};
+#[cfg(feature = "derive")]
+pub use variant_rs_derive::{FromVariant, ToVariant};
+pub use windows::Win32::System::Variant::VARENUM;
 
+// lets the derive macros refer to this crate as `::variant_rs` from within it
+extern crate self as variant_rs;
+
+pub mod coerce;
 pub mod com_types;
+pub mod compare;
 pub mod convert;
+#[cfg(not(all(feature = "portable", not(windows))))]
 pub mod dispatch;
+pub mod format;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod ops;
+pub mod owned;
+#[cfg(not(all(feature = "portable", not(windows))))]
+pub mod propvariant;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod sys;
 pub mod variant;
 
 #[doc(hidden)]
@@ -42,15 +64,19 @@ macro_rules! variant {
 
 #[cfg(test)]
 mod tests {
-    use crate::{ToVariant, Variant, VariantType};
+    use crate::com_types::record::{Record, RecordType};
+    use crate::com_types::safearray::{SafeArray, SafeArrayBound};
+    use crate::convert::VariantConversionError;
+    use crate::sys::{BSTR, CY, DECIMAL, DECIMAL_0, DECIMAL_0_0, DECIMAL_1, VARIANT};
+    use crate::{OwnedVariant, ToVariant, Variant, VariantType, VT_ARRAY, VT_BYREF};
     use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+    use rust_decimal::Decimal;
     use rust_decimal_macros::dec;
 
     use std::mem::ManuallyDrop;
-    use windows::core::BSTR;
-    use windows::Win32::Foundation::{DECIMAL, DECIMAL_0, DECIMAL_0_0, DECIMAL_1, VARIANT_BOOL};
-    use windows::Win32::System::Com::CY;
-    use windows::Win32::System::Variant::{VARENUM, VARIANT};
+    use windows::core::GUID;
+    use windows::Win32::Foundation::VARIANT_BOOL;
+    use windows::Win32::System::Variant::VARENUM;
 
     macro_rules! roundtrip
     {
@@ -89,6 +115,11 @@ mod tests {
         Variant::U64(0x55aa55aa55aa55aa)
     );
 
+    roundtrip!((VT_INT, intVal, -0x55aa55aa), Variant::Int(-0x55aa55aa));
+    roundtrip!((VT_UINT, uintVal, 0xaa55aa55), Variant::UInt(0xaa55aa55));
+    roundtrip!((VT_INT_PTR, llVal, -0x55aa), Variant::IntPtr(-0x55aa));
+    roundtrip!((VT_UINT_PTR, ullVal, 0x55aa), Variant::UIntPtr(0x55aa));
+
     roundtrip!((VT_R4, fltVal, 0.5f32), Variant::F32(0.5f32));
     roundtrip!((VT_R8, dblVal, 0.5f64), Variant::F64(0.5f64));
 
@@ -143,4 +174,1449 @@ mod tests {
         let back: Variant = ptr.try_into().unwrap(); // convert back
         assert_eq!(bstr, back);
     }
+
+    #[test]
+    fn array_of_variants() {
+        let arr = Variant::Array(SafeArray::from_vec(vec![
+            Variant::I32(1),
+            Variant::from("two"),
+            Variant::F64(3.0),
+            Variant::Null,
+        ]));
+        let cv: VARIANT = arr.clone().try_into().unwrap();
+        assert_eq!(
+            unsafe { cv.Anonymous.Anonymous.vt },
+            VARENUM(VariantType::VT_VARIANT.array())
+        );
+        assert_eq!(cv.try_into(), Ok(arr));
+    }
+
+    #[test]
+    fn typed_array() {
+        let arr = SafeArray::new(
+            VariantType::VT_R8,
+            vec![SafeArrayBound::new(1, 2), SafeArrayBound::new(-1, 3)],
+            (0..6).map(|i| Variant::F64(i as f64)).collect(),
+        )
+        .unwrap();
+        assert_eq!(arr.get(&[2, 0]), Some(&Variant::F64(3.0)));
+        assert_eq!(arr.get(&[0, 0]), None);
+
+        let cv: VARIANT = Variant::Array(arr.try_clone().unwrap()).try_into().unwrap();
+        assert_eq!(
+            unsafe { cv.Anonymous.Anonymous.vt },
+            VARENUM(VariantType::VT_R8.array())
+        );
+        assert_eq!(cv.try_into(), Ok(Variant::Array(arr)));
+    }
+
+    #[test]
+    fn typed_array_strings() {
+        let arr = Variant::Array(
+            SafeArray::new(
+                VariantType::VT_BSTR,
+                vec![SafeArrayBound::new(0, 2)],
+                vec!["a".into(), "b".into()],
+            )
+            .unwrap(),
+        );
+        let cv: VARIANT = arr.clone().try_into().unwrap();
+        assert_eq!(cv.try_into(), Ok(arr));
+    }
+
+    #[test]
+    fn typed_array_mismatch() {
+        let arr = SafeArray::new(
+            VariantType::VT_I2,
+            vec![SafeArrayBound::new(0, 1)],
+            vec![Variant::I32(1)],
+        )
+        .unwrap();
+        let cv: Result<VARIANT, _> = Variant::Array(arr).try_into();
+        assert_eq!(
+            cv.err(),
+            Some(VariantConversionError::ArrayElementMismatch(
+                VariantType::VT_I2
+            ))
+        );
+    }
+
+    #[test]
+    fn array_without_dimensions() {
+        use windows::Win32::System::Com::SAFEARRAY;
+
+        let mut value = 5i32;
+        let arr = SAFEARRAY {
+            cbElements: 4,
+            pvData: &mut value as *mut i32 as *mut _,
+            ..Default::default()
+        };
+        let copy = unsafe { SafeArray::copy_from_raw(&arr, VariantType::VT_I4) }.unwrap();
+        assert!(copy.is_empty());
+        assert!(copy.bounds().is_empty());
+    }
+
+    #[test]
+    fn locked_array() {
+        use crate::sys::SafeArrayDestroy;
+        use windows::Win32::Foundation::DISP_E_ARRAYISLOCKED;
+
+        let psa = SafeArray::from_vec(vec![Variant::I16(5)])
+            .into_raw()
+            .unwrap();
+        unsafe { (*psa).cLocks = 1 };
+        let res = unsafe { SafeArray::from_raw(psa, VariantType::VT_VARIANT) };
+        assert_eq!(
+            res,
+            Err(VariantConversionError::ComError(DISP_E_ARRAYISLOCKED))
+        );
+        unsafe {
+            (*psa).cLocks = 0;
+            let _ = SafeArrayDestroy(psa);
+        }
+    }
+
+    #[test]
+    fn array_byref() {
+        let arr = SafeArray::from_vec(vec![Variant::I16(5)]);
+        let mut psa = arr.into_raw().unwrap();
+        let mut cv = variant!(
+            VariantType::VT_VARIANT.array() | VT_BYREF,
+            pparray,
+            &mut psa
+        );
+        let conv = Variant::try_from(&mut cv).unwrap();
+        let back: VARIANT = conv.try_into().unwrap();
+        unsafe {
+            assert_eq!(
+                back.Anonymous.Anonymous.vt.0,
+                VT_ARRAY | VT_BYREF | VariantType::VT_VARIANT as u16
+            );
+            assert_eq!(
+                back.Anonymous.Anonymous.Anonymous.pparray,
+                &mut psa as *mut _
+            );
+            let owned = SafeArray::from_raw(psa, VariantType::VT_VARIANT).unwrap();
+            assert_eq!(owned.elements(), &[Variant::I16(5)]);
+        }
+    }
+
+    #[test]
+    fn record_without_info() {
+        let rec = Variant::Record(Record::new(
+            RecordType::new("Point", GUID::zeroed()),
+            vec![("X".into(), Variant::I32(1)), ("Y".into(), Variant::I32(2))],
+        ));
+        assert_eq!(rec.clone().expect_record().get("x"), Some(&Variant::I32(1)));
+        let cv: Result<VARIANT, _> = rec.try_into();
+        assert_eq!(cv.err(), Some(VariantConversionError::MissingRecordInfo));
+
+        let raw = variant!(VariantType::VT_RECORD);
+        let conv: Result<Variant, _> = raw.try_into();
+        assert_eq!(conv, Err(VariantConversionError::MissingRecordInfo));
+    }
+
+    #[test]
+    #[cfg(all(feature = "portable", not(windows)))]
+    fn portable_unsupported() {
+        use crate::sys::SafeArrayDestroy;
+        use std::ffi::c_void;
+        use windows::core::Interface;
+        use windows::Win32::Foundation::E_INVALIDARG;
+        use windows::Win32::System::Com::SAFEARRAY;
+        use windows::Win32::System::Ole::IRecordInfo;
+
+        let empty = SAFEARRAY::default();
+        assert_eq!(unsafe { SafeArrayDestroy(&empty) }, E_INVALIDARG);
+
+        // an object whose only methods are AddRef and Release, as the fields are never accessed
+        unsafe extern "system" fn add_ref_release(_this: *mut c_void) -> u32 {
+            1
+        }
+        static VTABLE: [unsafe extern "system" fn(*mut c_void) -> u32; 3] = [add_ref_release; 3];
+        static OBJECT: &[unsafe extern "system" fn(*mut c_void) -> u32; 3] = &VTABLE;
+        let info = unsafe { IRecordInfo::from_raw(&OBJECT as *const _ as *mut c_void) };
+
+        let mut record_type = RecordType::new("Point", GUID::zeroed());
+        record_type.info = Some(info.clone());
+        let rec = Variant::Record(Record::new(
+            record_type,
+            vec![("X".into(), Variant::I32(1))],
+        ));
+        assert_eq!(
+            OwnedVariant::try_from(rec).err(),
+            Some(VariantConversionError::Unimplemented(
+                VariantType::VT_RECORD
+            ))
+        );
+
+        let data = 0u64;
+        let read = unsafe { Record::from_raw(&data as *const _ as *const c_void, Some(&info)) };
+        assert_eq!(
+            read,
+            Err(VariantConversionError::Unimplemented(
+                VariantType::VT_RECORD
+            ))
+        );
+    }
+
+    #[test]
+    fn borrowed_reference() {
+        let mut value = 5i32;
+        let mut cv = variant!(VariantType::VT_I4.byref(), plVal, &mut value);
+        assert_eq!(
+            TryInto::<Variant>::try_into(cv.clone()),
+            Err(VariantConversionError::BorrowedReference(VARENUM(
+                VariantType::VT_I4.byref()
+            )))
+        );
+
+        match Variant::try_from(&mut cv).unwrap() {
+            Variant::I32Ref(r) => *r = 6,
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(value, 6);
+    }
+
+    #[test]
+    fn borrowed_value() {
+        let mut cv: VARIANT = Variant::from("moved").try_into().unwrap();
+        assert_eq!(Variant::try_from(&mut cv), Ok(Variant::from("moved")));
+        assert_eq!(
+            unsafe { cv.Anonymous.Anonymous.vt },
+            VARENUM(VariantType::VT_EMPTY as u16)
+        );
+    }
+
+    #[test]
+    fn owned_variant() {
+        let owned = OwnedVariant::try_from(Variant::from("owned")).unwrap();
+        assert_eq!(owned.vt(), VARENUM(VariantType::VT_BSTR as u16));
+        let raw = owned.into_raw();
+        let owned = unsafe { OwnedVariant::from_raw(raw) };
+        assert_eq!(Variant::try_from(owned), Ok(Variant::from("owned")));
+
+        let mut out = OwnedVariant::try_from(Variant::from(vec![1, 2])).unwrap();
+        out.clear();
+        assert_eq!(out.vt(), VARENUM(VariantType::VT_EMPTY as u16));
+        unsafe { *out.as_mut_ptr() = Variant::I32(3).try_into().unwrap() };
+        assert_eq!(Variant::try_from(out), Ok(Variant::I32(3)));
+    }
+
+    #[test]
+    fn copied_value() {
+        let owned = OwnedVariant::try_from(Variant::from("copied")).unwrap();
+        assert_eq!(
+            Variant::try_from(owned.as_raw()),
+            Ok(Variant::from("copied"))
+        );
+        assert_eq!(Variant::try_from(owned), Ok(Variant::from("copied")));
+
+        let arr = Variant::from(vec!["a", "b"]);
+        let owned = OwnedVariant::try_from(arr.clone()).unwrap();
+        assert_eq!(Variant::try_from(owned.as_raw()), Ok(arr.clone()));
+        assert_eq!(Variant::try_from(owned), Ok(arr));
+
+        let mut value = 5i32;
+        let cv = variant!(VariantType::VT_I4.byref(), plVal, &mut value);
+        assert_eq!(Variant::try_from(&cv), Ok(Variant::I32(5)));
+    }
+
+    #[test]
+    fn change_type() {
+        use crate::coerce::{VARIANT_ALPHABOOL, VAR_CHANGE_FLAGS};
+        use VariantType::*;
+        let none = VAR_CHANGE_FLAGS(0);
+        let change = |v: Variant<'static>, vt| v.change_type(vt, none);
+
+        assert_eq!(change(Variant::I32(300), VT_I2), Ok(Variant::I16(300)));
+        assert_eq!(
+            change(Variant::I32(300), VT_UI1),
+            Err(VariantConversionError::Overflow(VT_UI1))
+        );
+        assert_eq!(change(Variant::F64(2.5), VT_I4), Ok(Variant::I32(2)));
+        assert_eq!(change(Variant::F64(3.5), VT_I4), Ok(Variant::I32(4)));
+        assert_eq!(change(Variant::Bool(true), VT_I4), Ok(Variant::I32(-1)));
+        assert_eq!(change(Variant::Bool(true), VT_UI1), Ok(Variant::U8(255)));
+        assert_eq!(change(Variant::Empty, VT_I4), Ok(Variant::I32(0)));
+        assert_eq!(
+            change(Variant::Null, VT_I4),
+            Err(VariantConversionError::TypeMismatch(VT_I4))
+        );
+
+        assert_eq!(change(" 1,234.5 ".into(), VT_R8), Ok(Variant::F64(1234.5)));
+        assert_eq!(change("&HFF".into(), VT_I2), Ok(Variant::I16(255)));
+        assert_eq!(change("(12)".into(), VT_I4), Ok(Variant::I32(-12)));
+        assert_eq!(
+            change("12.34567".into(), VT_CY),
+            Ok(Variant::Currency(dec!(12.3457).into()))
+        );
+        assert_eq!(
+            change("abc".into(), VT_I4),
+            Err(VariantConversionError::TypeMismatch(VT_I4))
+        );
+        for s in ["€", "a€", "&€"] {
+            assert_eq!(
+                change(s.into(), VT_I4),
+                Err(VariantConversionError::TypeMismatch(VT_I4))
+            );
+            assert_eq!(
+                change(s.into(), VT_DATE),
+                Err(VariantConversionError::TypeMismatch(VT_DATE))
+            );
+            assert_eq!(
+                change(s.into(), VT_BOOL),
+                Err(VariantConversionError::TypeMismatch(VT_BOOL))
+            );
+        }
+        assert_eq!(change("true".into(), VT_BOOL), Ok(Variant::Bool(true)));
+        assert_eq!(change("0".into(), VT_BOOL), Ok(Variant::Bool(false)));
+
+        assert_eq!(change(Variant::Bool(true), VT_BSTR), Ok("-1".into()));
+        assert_eq!(
+            Variant::Bool(true).change_type(VT_BSTR, VARIANT_ALPHABOOL),
+            Ok("True".into())
+        );
+        assert_eq!(change(Variant::F64(0.1 + 0.2), VT_BSTR), Ok("0.3".into()));
+        assert_eq!(change(Variant::F64(1e15), VT_BSTR), Ok("1E+15".into()));
+        assert_eq!(change(Variant::F64(0.00001), VT_BSTR), Ok("1E-05".into()));
+        assert_eq!(change(Variant::F32(0.1), VT_BSTR), Ok("0.1".into()));
+        assert_eq!(change(Variant::I64(-5), VT_BSTR), Ok("-5".into()));
+
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        assert_eq!(
+            change("3/1/2024 2:30 PM".into(), VT_DATE),
+            Ok(Variant::Date(date.and_hms_opt(14, 30, 0).unwrap()))
+        );
+        assert_eq!(
+            change(Variant::Date(date.and_hms_opt(0, 0, 0).unwrap()), VT_BSTR),
+            Ok("3/1/2024".into())
+        );
+        assert_eq!(
+            change(Variant::Date(date.and_hms_opt(14, 30, 5).unwrap()), VT_BSTR),
+            Ok("3/1/2024 2:30:05 PM".into())
+        );
+        assert_eq!(
+            change(Variant::F64(1e10), VT_DATE),
+            Err(VariantConversionError::Overflow(VT_DATE))
+        );
+
+        let mut value = 7i16;
+        let byref = Variant::I16Ref(&mut value);
+        assert_eq!(byref.change_type(VT_BSTR, none), Ok("7".into()));
+    }
+
+    #[test]
+    fn arithmetic() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let midnight = |d: NaiveDate| d.and_hms_opt(0, 0, 0).unwrap();
+
+        assert_eq!(Variant::I16(1) + Variant::I32(2), Ok(Variant::I32(3)));
+        assert_eq!(
+            Variant::I16(i16::MAX) + Variant::I16(1),
+            Ok(Variant::I32(32768))
+        );
+        assert_eq!(Variant::U8(1) - Variant::U8(2), Ok(Variant::I16(-1)));
+        assert_eq!(
+            Variant::I32(i32::MAX) * Variant::I32(2),
+            Ok(Variant::F64(i32::MAX as f64 * 2.0))
+        );
+        assert_eq!(
+            Variant::I64(i64::MAX) + Variant::I64(1),
+            Err(VariantConversionError::Overflow(VariantType::VT_I8))
+        );
+        assert_eq!(
+            Variant::Currency(dec!(1.5).into()) * Variant::F64(2.0),
+            Ok(Variant::F64(3.0))
+        );
+        assert_eq!(
+            Variant::Currency(dec!(1.5).into()) + Variant::I32(1),
+            Ok(Variant::Currency(dec!(2.5).into()))
+        );
+        assert_eq!(
+            Variant::Date(midnight(date)) + Variant::I32(1),
+            Ok(Variant::Date(midnight(date.succ_opt().unwrap())))
+        );
+        assert_eq!(
+            Variant::Date(midnight(date)) - Variant::Date(midnight(date)),
+            Ok(Variant::F64(0.0))
+        );
+        assert_eq!(Variant::Null + Variant::I32(1), Ok(Variant::Null));
+        assert_eq!(Variant::from("a") + Variant::from("b"), Ok("ab".into()));
+        assert_eq!(Variant::from("1") + Variant::I32(2), Ok(Variant::F64(3.0)));
+        assert_eq!(Variant::Empty + Variant::Empty, Ok(Variant::I16(0)));
+
+        assert_eq!(Variant::I32(7) / Variant::I32(2), Ok(Variant::F64(3.5)));
+        assert_eq!(Variant::I16(7) / Variant::I16(2), Ok(Variant::F32(3.5)));
+        assert_eq!(
+            Variant::I32(1) / Variant::I32(0),
+            Err(VariantConversionError::DivisionByZero)
+        );
+        assert_eq!(
+            Variant::Decimal(dec!(1)) / Variant::I32(4),
+            Ok(Variant::Decimal(dec!(0.25)))
+        );
+        assert_eq!(Variant::F64(7.5) % Variant::I32(3), Ok(Variant::I32(2)));
+        assert_eq!(Variant::U8(7) % Variant::U8(3), Ok(Variant::U8(1)));
+
+        assert_eq!(-Variant::U8(1), Ok(Variant::I16(-1)));
+        assert_eq!(-Variant::I16(i16::MIN), Ok(Variant::I32(32768)));
+        assert_eq!(-Variant::Null, Ok(Variant::Null));
+        assert_eq!(
+            Variant::I32(2).pow(Variant::I32(10)),
+            Ok(Variant::F64(1024.0))
+        );
+    }
+
+    #[test]
+    fn compare() {
+        use crate::compare::{CompareOptions, VariantOrdering};
+        let cmp = |a: Variant, b: Variant| a.compare(&b, CompareOptions::default());
+
+        assert_eq!(
+            cmp(Variant::I32(1), Variant::F64(1.0)),
+            Ok(VariantOrdering::Equal)
+        );
+        assert_eq!(
+            cmp(Variant::U8(2), Variant::Decimal(dec!(2.5))),
+            Ok(VariantOrdering::Less)
+        );
+        assert_eq!(
+            cmp(Variant::Bool(true), Variant::I16(0)),
+            Ok(VariantOrdering::Less)
+        );
+        assert_eq!(
+            cmp(Variant::Empty, Variant::I32(0)),
+            Ok(VariantOrdering::Equal)
+        );
+        assert_eq!(cmp(Variant::Empty, "".into()), Ok(VariantOrdering::Equal));
+        assert_eq!(
+            cmp(Variant::I32(100), "1".into()),
+            Ok(VariantOrdering::Less)
+        );
+        assert_eq!(cmp(Variant::Null, Variant::Null), Ok(VariantOrdering::Null));
+        assert_eq!(cmp("a".into(), "B".into()), Ok(VariantOrdering::Greater));
+        assert_eq!(
+            Variant::from("a").compare(&"B".into(), CompareOptions { ignore_case: true }),
+            Ok(VariantOrdering::Less)
+        );
+        assert_eq!(
+            cmp(Variant::from(vec![1]), Variant::I32(1)),
+            Err(VariantConversionError::TypeMismatch(
+                VariantType::VT_VARIANT
+            ))
+        );
+
+        // the OLE values of these dates are -1.25 and -1.75
+        let day = NaiveDate::from_ymd_opt(1899, 12, 29).unwrap();
+        let morning = Variant::Date(day.and_hms_opt(6, 0, 0).unwrap());
+        let evening = Variant::Date(day.and_hms_opt(18, 0, 0).unwrap());
+        assert_eq!(
+            cmp(morning.clone(), evening.clone()),
+            Ok(VariantOrdering::Less)
+        );
+        assert!(morning < evening);
+        let before = Variant::Date(
+            NaiveDate::from_ymd_opt(1899, 12, 28)
+                .unwrap()
+                .and_hms_opt(23, 0, 0)
+                .unwrap(),
+        );
+        assert!(before < morning);
+
+        let mut value = 3i64;
+        assert!(Variant::I64Ref(&mut value) > Variant::F32(2.5));
+        assert!(Variant::Null.partial_cmp(&Variant::I32(1)).is_none());
+        assert_eq!(
+            Variant::I32(1).compare(&Variant::F64(1.0), Default::default()),
+            Ok(VariantOrdering::Equal)
+        );
+        assert!(Variant::I32(1).partial_cmp(&Variant::F64(1.0)).is_none());
+        assert_eq!(
+            Variant::I32(1).partial_cmp(&Variant::I32(1)),
+            Some(std::cmp::Ordering::Equal)
+        );
+        let mut values = vec![Variant::F64(2.5), Variant::I32(1), Variant::U8(2)];
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(values, [Variant::I32(1), Variant::U8(2), Variant::F64(2.5)]);
+    }
+
+    #[test]
+    fn display() {
+        use crate::format::{DateOrder, DisplaySettings, NullMode};
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+
+        assert_eq!(Variant::Bool(true).to_string(), "True");
+        assert_eq!(Variant::F64(0.1 + 0.2).to_string(), "0.30000000000000004");
+        assert_eq!(Variant::F64(1e15).to_string(), "1E+15");
+        assert_eq!(Variant::F64(-0.00001).to_string(), "-1E-05");
+        assert_eq!(Variant::F32(0.1).to_string(), "0.1");
+        assert_eq!(Variant::Currency(dec!(12.5000).into()).to_string(), "12.5");
+        assert_eq!(Variant::Empty.to_string(), "");
+        assert_eq!(Variant::Null.to_string(), "Null");
+        assert_eq!(
+            Variant::Date(date.and_hms_opt(14, 30, 5).unwrap()).to_string(),
+            "3/1/2024 2:30:05 PM"
+        );
+        assert_eq!(format!("{:>4}", Variant::I32(7)), "   7");
+
+        let mut value = 2.5f64;
+        assert_eq!(Variant::F64Ref(&mut value).to_string(), "2.5");
+
+        let settings = DisplaySettings {
+            decimal_separator: ',',
+            date_separator: '.',
+            date_order: DateOrder::DayMonthYear,
+            hour24: true,
+            null_mode: NullMode::Error,
+        };
+        assert_eq!(Variant::F64(2.5).format(&settings), Ok("2,5".into()));
+        assert_eq!(
+            Variant::Date(date.and_hms_opt(14, 30, 5).unwrap()).format(&settings),
+            Ok("1.3.2024 14:30:05".into())
+        );
+        assert_eq!(
+            Variant::Null.format(&settings),
+            Err(VariantConversionError::TypeMismatch(VariantType::VT_BSTR))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_roundtrip() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let values = vec![
+            Variant::Empty,
+            Variant::Null,
+            Variant::I16(5),
+            Variant::U64(u64::MAX),
+            Variant::F32(0.5),
+            Variant::Currency(dec!(12.3456).into()),
+            Variant::Decimal(dec!(-1.000000000000000000000000001)),
+            Variant::Date(date.and_hms_opt(14, 30, 5).unwrap()),
+            Variant::from("text"),
+            Variant::Dispatch(None),
+            Variant::Error(windows::core::HRESULT(0x800A07FAu32 as i32)),
+            Variant::Array(SafeArray::from_vec(vec![Variant::I32(1), "two".into()])),
+            Variant::Record(Record::new(
+                RecordType::new("Point", GUID::from_u128(0x0123456789abcdef0123456789abcdef)),
+                vec![("X".into(), Variant::I32(1))],
+            )),
+        ];
+        for value in values {
+            let json = serde_json::to_string(&value).unwrap();
+            let back: Variant = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, value, "{}", json);
+        }
+        assert_eq!(
+            serde_json::to_string(&Variant::I32(5)).unwrap(),
+            r#"{"I32":5}"#
+        );
+
+        let mut value = 5i32;
+        assert!(serde_json::to_string(&Variant::I32Ref(&mut value)).is_err());
+        let raw = variant!(VariantType::VT_FILETIME, llVal, 1);
+        let raw = Variant::decode(raw, crate::convert::DecodeMode::Lenient).unwrap();
+        assert_eq!(
+            serde_json::to_string(&raw).unwrap_err().to_string(),
+            "raw variants can't be serialized: VT_FILETIME"
+        );
+        let invalid =
+            r#"{"Array":{"element_type":"VT_I4","bounds":[{"lower":0,"len":2}],"elements":[]}}"#;
+        assert!(serde_json::from_str::<Variant>(invalid).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn json() {
+        use crate::json::JsonPolicy;
+        use serde_json::json;
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let to_json = |v: Variant| v.to_json(JsonPolicy::Error);
+
+        assert_eq!(to_json(Variant::I16(5)), Ok(json!(5)));
+        assert_eq!(to_json(Variant::Empty), Ok(json!(null)));
+        assert_eq!(to_json(Variant::Decimal(dec!(1.50))), Ok(json!("1.50")));
+        assert_eq!(
+            to_json(Variant::Date(date.and_hms_opt(14, 30, 5).unwrap())),
+            Ok(json!("2024-03-01T14:30:05"))
+        );
+        let matrix = SafeArray::new(
+            VariantType::VT_I4,
+            vec![SafeArrayBound::new(1, 2), SafeArrayBound::new(0, 3)],
+            (0..6).map(Variant::I32).collect(),
+        )
+        .unwrap();
+        assert_eq!(
+            to_json(Variant::Array(matrix)),
+            Ok(json!([[0, 2, 4], [1, 3, 5]]))
+        );
+
+        let error = Variant::Error(windows::core::HRESULT(-1));
+        assert_eq!(
+            to_json(Variant::F64(f64::NAN)),
+            Err(VariantConversionError::Unrepresentable(VariantType::VT_R8))
+        );
+        assert_eq!(error.to_json(JsonPolicy::Null), Ok(json!(null)));
+        assert!(error.to_json(JsonPolicy::Debug).unwrap().is_string());
+
+        let value = json!({"a": [1, 2.5, "x", null], "b": 10000000000i64});
+        let var = Variant::from_json(&value);
+        let rec = var.clone().expect_record();
+        assert_eq!(rec.get("b"), Some(&Variant::I64(10000000000)));
+        assert_eq!(
+            rec.get("a"),
+            Some(&Variant::Array(SafeArray::from_vec(vec![
+                Variant::I32(1),
+                Variant::F64(2.5),
+                "x".into(),
+                Variant::Null
+            ])))
+        );
+        assert_eq!(to_json(var), Ok(value));
+
+        let rec = Variant::Record(Record::new(
+            RecordType::new("Point", GUID::zeroed()),
+            vec![
+                ("y".to_string(), Variant::I32(2)),
+                ("x".to_string(), Variant::I32(1)),
+            ],
+        ));
+        assert_eq!(
+            to_json(rec).unwrap().to_string(),
+            r#"{"y":2,"x":1}"#.to_string()
+        );
+    }
+
+    #[test]
+    fn from_variant() {
+        use crate::FromVariant;
+        assert_eq!(Variant::I16(5).get::<i32>(), Ok(5));
+        assert_eq!(Variant::from("12").get::<i64>(), Ok(12));
+        assert_eq!(Variant::I32(5).get::<String>(), Ok("5".to_string()));
+        assert_eq!(Variant::F64(2.5).get::<Decimal>(), Ok(dec!(2.5)));
+        assert_eq!(
+            Variant::I32(300).get::<u8>(),
+            Err(VariantConversionError::Overflow(VariantType::VT_UI1))
+        );
+        assert_eq!(Variant::Null.get::<Option<i32>>(), Ok(None));
+        assert_eq!(Variant::I32(1).get::<Option<bool>>(), Ok(Some(true)));
+        assert_eq!(
+            Variant::Null.get::<i32>(),
+            Err(VariantConversionError::TypeMismatch(VariantType::VT_I4))
+        );
+
+        let arr = Variant::from(vec![1, 2, 3]);
+        assert_eq!(arr.clone().get::<Vec<f64>>(), Ok(vec![1.0, 2.0, 3.0]));
+        assert_eq!(
+            arr.clone().get::<(i32, String, u8)>(),
+            Ok((1, "2".to_string(), 3))
+        );
+        assert_eq!(
+            arr.get::<(i32, i32)>(),
+            Err(VariantConversionError::ArrayBoundsMismatch)
+        );
+        assert_eq!(
+            i32::from_variant(Variant::from(vec![1])),
+            Err(VariantConversionError::TypeMismatch(VariantType::VT_I4))
+        );
+
+        let mut value = 7u16;
+        assert_eq!(Variant::U16Ref(&mut value).get::<u32>(), Ok(7));
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn derive() {
+        use crate::{FromVariant, ToVariant};
+
+        #[derive(Clone, Debug, PartialEq, ToVariant, FromVariant)]
+        struct Point {
+            x: i32,
+            #[variant(rename = "Y")]
+            y: f64,
+            label: String,
+        }
+
+        #[derive(Clone, Debug, PartialEq, ToVariant, FromVariant)]
+        #[variant(array)]
+        struct Pair {
+            a: i16,
+            b: String,
+        }
+
+        #[derive(Clone, Debug, PartialEq, ToVariant, FromVariant)]
+        struct Id(u32);
+
+        #[derive(Clone, Debug, PartialEq, ToVariant, FromVariant)]
+        struct Wrapper<T> {
+            value: T,
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq, ToVariant, FromVariant)]
+        enum Color {
+            Red = 1,
+            Green = 2,
+            Blue = 4,
+        }
+
+        let point = Point {
+            x: 1,
+            y: 2.5,
+            label: "A".to_string(),
+        };
+        let var = point.clone().to_variant();
+        assert_eq!(
+            var,
+            Variant::Record(Record::new(
+                RecordType::new("Point", GUID::zeroed()),
+                vec![
+                    ("x".to_string(), Variant::I32(1)),
+                    ("Y".to_string(), Variant::F64(2.5)),
+                    ("label".to_string(), "A".into()),
+                ]
+            ))
+        );
+        assert_eq!(var.get::<Point>(), Ok(point));
+        assert_eq!(
+            Point::from_variant(Variant::Record(Record::new(
+                RecordType::new("Point", GUID::zeroed()),
+                vec![("x".to_string(), Variant::I32(1))]
+            ))),
+            Err(VariantConversionError::MissingRecordField("Y".to_string()))
+        );
+
+        let pair = Pair {
+            a: 3,
+            b: "b".to_string(),
+        };
+        let var = pair.clone().to_variant();
+        assert_eq!(
+            var,
+            Variant::Array(SafeArray::from_vec(vec![Variant::I16(3), "b".into()]))
+        );
+        assert_eq!(var.get::<Pair>(), Ok(pair));
+        assert_eq!(
+            Variant::from(vec![1]).get::<Pair>(),
+            Err(VariantConversionError::ArrayBoundsMismatch)
+        );
+
+        let wrapper = Wrapper { value: 7i16 };
+        assert_eq!(wrapper.clone().to_variant().get(), Ok(wrapper));
+
+        assert_eq!(Id(5).to_variant(), Variant::U32(5));
+        assert_eq!(Variant::I32(5).get::<Id>(), Ok(Id(5)));
+
+        assert_eq!(Color::Blue.to_variant(), Variant::I32(4));
+        assert_eq!(Variant::from("2").get::<Color>(), Ok(Color::Green));
+        assert_eq!(
+            Variant::I32(3).get::<Color>(),
+            Err(VariantConversionError::InvalidEnumValue(3))
+        );
+        assert_eq!(Color::Red.to_variant().get::<Color>(), Ok(Color::Red));
+    }
+
+    #[cfg(all(feature = "derive", not(all(feature = "portable", not(windows)))))]
+    #[test]
+    fn derive_record_info() {
+        use crate::ToVariant;
+        use std::cell::RefCell;
+        use std::ffi::c_void;
+        use windows::core::{IUnknown_Vtbl, Interface, HRESULT, PCWSTR};
+        use windows::Win32::Foundation::{E_NOTIMPL, S_OK};
+        use windows::Win32::System::Ole::{IRecordInfo, IRecordInfo_Vtbl};
+
+        // a static record info that only supports creating records and storing their fields
+        thread_local! {
+            static FIELDS: RefCell<Vec<(String, Variant<'static>)>> = RefCell::new(Vec::new());
+        }
+
+        unsafe extern "system" fn query_interface(
+            _this: *mut c_void,
+            _iid: *const GUID,
+            _object: *mut *mut c_void,
+        ) -> HRESULT {
+            E_NOTIMPL
+        }
+        unsafe extern "system" fn add_ref_release(_this: *mut c_void) -> u32 {
+            1
+        }
+        unsafe extern "system" fn unsupported(_this: *mut c_void) -> HRESULT {
+            E_NOTIMPL
+        }
+        unsafe extern "system" fn clear(_this: *mut c_void, _data: *const c_void) -> HRESULT {
+            S_OK
+        }
+        unsafe extern "system" fn create(this: *mut c_void) -> *mut c_void {
+            this
+        }
+        unsafe extern "system" fn put_field(
+            _this: *mut c_void,
+            _flags: u32,
+            _data: *mut c_void,
+            name: PCWSTR,
+            value: *const VARIANT,
+        ) -> HRESULT {
+            let field = (name.to_string().unwrap(), (&*value).try_into().unwrap());
+            FIELDS.with(|fields| fields.borrow_mut().push(field));
+            S_OK
+        }
+
+        static VTABLE: IRecordInfo_Vtbl = unsafe {
+            IRecordInfo_Vtbl {
+                base__: IUnknown_Vtbl {
+                    QueryInterface: query_interface,
+                    AddRef: add_ref_release,
+                    Release: add_ref_release,
+                },
+                RecordInit: std::mem::transmute(unsupported as *const ()),
+                RecordClear: clear,
+                RecordCopy: std::mem::transmute(unsupported as *const ()),
+                GetGuid: std::mem::transmute(unsupported as *const ()),
+                GetName: std::mem::transmute(unsupported as *const ()),
+                GetSize: std::mem::transmute(unsupported as *const ()),
+                GetTypeInfo: std::mem::transmute(unsupported as *const ()),
+                GetField: std::mem::transmute(unsupported as *const ()),
+                GetFieldNoCopy: std::mem::transmute(unsupported as *const ()),
+                PutField: put_field,
+                PutFieldNoCopy: std::mem::transmute(unsupported as *const ()),
+                GetFieldNames: std::mem::transmute(unsupported as *const ()),
+                IsMatchingType: std::mem::transmute(unsupported as *const ()),
+                RecordCreate: create,
+                RecordCreateCopy: std::mem::transmute(unsupported as *const ()),
+                RecordDestroy: clear,
+            }
+        };
+        static INFO: &IRecordInfo_Vtbl = &VTABLE;
+
+        fn point_info() -> Option<IRecordInfo> {
+            Some(unsafe { IRecordInfo::from_raw(&INFO as *const _ as *mut c_void) })
+        }
+
+        #[derive(ToVariant)]
+        #[variant(record_info = point_info)]
+        struct Point {
+            x: i32,
+            y: f64,
+        }
+
+        let var = Point { x: 1, y: 2.5 }.to_variant();
+        let owned = OwnedVariant::try_from(var).unwrap();
+        assert_eq!(owned.vt(), VARENUM(VariantType::VT_RECORD as u16));
+        assert_eq!(
+            FIELDS.with(|fields| fields.take()),
+            vec![
+                ("x".to_string(), Variant::I32(1)),
+                ("y".to_string(), Variant::F64(2.5)),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(not(all(feature = "portable", not(windows))))]
+    fn propvariant() {
+        use crate::propvariant::{ClipData, PropVariant};
+        use widestring::U16CString;
+        use windows::Win32::System::Com::StructuredStorage::PROPVARIANT;
+
+        fn roundtrip(value: PropVariant<'static>) {
+            let mut raw: PROPVARIANT = value.clone().try_into().unwrap();
+            assert_eq!(PropVariant::try_from(&raw), Ok(value.clone()), "copy");
+            let copy: PROPVARIANT = PropVariant::try_from(&raw).unwrap().try_into().unwrap();
+            assert_eq!(PropVariant::try_from(&mut raw), Ok(value.clone()), "borrow");
+            assert_eq!(PropVariant::try_from(copy), Ok(value), "owned");
+        }
+
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1)
+            .unwrap()
+            .and_hms_nano_opt(14, 30, 5, 123456700)
+            .unwrap();
+        let guid = GUID::from_u128(0x0123456789abcdef0123456789abcdef);
+        let wide = |s| U16CString::from_str(s).unwrap();
+
+        roundtrip(Variant::I32(5).into());
+        roundtrip(Variant::from("text").into());
+        roundtrip(Variant::Array(SafeArray::from_vec(vec![Variant::I16(1)])).into());
+        roundtrip(PropVariant::AnsiString(c"ansi".into()));
+        roundtrip(PropVariant::WideString(wide("wide")));
+        roundtrip(PropVariant::FileTime(date));
+        roundtrip(PropVariant::Blob(vec![1, 2, 3]));
+        roundtrip(PropVariant::Blob(vec![]));
+        roundtrip(PropVariant::Clsid(guid));
+        roundtrip(PropVariant::ClipData(ClipData {
+            format: 3,
+            data: vec![4, 5],
+        }));
+        roundtrip(PropVariant::Stream(None));
+        roundtrip(PropVariant::Vector(
+            VariantType::VT_I4,
+            vec![Variant::I32(1).into(), Variant::I32(2).into()],
+        ));
+        roundtrip(PropVariant::Vector(
+            VariantType::VT_BSTR,
+            vec![Variant::from("a").into(), Variant::from("b").into()],
+        ));
+        roundtrip(PropVariant::Vector(
+            VariantType::VT_LPWSTR,
+            vec![PropVariant::WideString(wide("a"))],
+        ));
+        roundtrip(PropVariant::Vector(
+            VariantType::VT_CLSID,
+            vec![PropVariant::Clsid(guid), PropVariant::Clsid(GUID::zeroed())],
+        ));
+        roundtrip(PropVariant::Vector(
+            VariantType::VT_VARIANT,
+            vec![
+                Variant::F64(1.5).into(),
+                PropVariant::FileTime(date),
+                PropVariant::Vector(VariantType::VT_UI1, vec![Variant::U8(7).into()]),
+            ],
+        ));
+        roundtrip(PropVariant::Vector(VariantType::VT_R8, vec![]));
+
+        assert_eq!(
+            PROPVARIANT::try_from(PropVariant::Vector(
+                VariantType::VT_BSTR,
+                vec![Variant::from("a").into(), Variant::I32(1).into()],
+            ))
+            .err(),
+            Some(VariantConversionError::ArrayElementMismatch(
+                VariantType::VT_BSTR
+            ))
+        );
+        assert_eq!(
+            PROPVARIANT::try_from(PropVariant::Vector(VariantType::VT_DISPATCH, vec![])).err(),
+            Some(VariantConversionError::InvalidArrayType(
+                VariantType::VT_DISPATCH
+            ))
+        );
+        assert_eq!(
+            PROPVARIANT::try_from(PropVariant::FileTime(
+                NaiveDate::from_ymd_opt(1600, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap()
+            ))
+            .err(),
+            Some(VariantConversionError::Overflow(VariantType::VT_FILETIME))
+        );
+        assert_eq!(
+            TryInto::<Variant>::try_into(variant!(VariantType::VT_FILETIME)),
+            Err(VariantConversionError::PropVariantOnly(
+                VariantType::VT_FILETIME.into()
+            ))
+        );
+    }
+
+    #[test]
+    fn int_types_keep_vt() {
+        for vt in [
+            VariantType::VT_INT,
+            VariantType::VT_UINT,
+            VariantType::VT_INT_PTR,
+            VariantType::VT_UINT_PTR,
+        ] {
+            let value: Variant = variant!(vt, lVal, 7).try_into().unwrap();
+            let raw: VARIANT = value.try_into().unwrap();
+            assert_eq!(unsafe { raw.Anonymous.Anonymous.vt }, VARENUM(vt as u16));
+        }
+
+        let mut value = 5;
+        let raw: VARIANT = Variant::IntRef(&mut value).try_into().unwrap();
+        assert_eq!(
+            unsafe { raw.Anonymous.Anonymous.vt },
+            VARENUM(VariantType::VT_INT.byref())
+        );
+
+        let arr = SafeArray::new(
+            VariantType::VT_INT,
+            vec![SafeArrayBound::new(0, 2)],
+            vec![Variant::Int(1), Variant::Int(2)],
+        )
+        .unwrap();
+        let raw: VARIANT = Variant::Array(arr.try_clone().unwrap()).try_into().unwrap();
+        assert_eq!(raw.try_into(), Ok(Variant::Array(arr)));
+
+        assert_eq!(
+            Variant::I32(3).change_type(VariantType::VT_UINT, Default::default()),
+            Ok(Variant::UInt(3))
+        );
+        assert_eq!(Variant::Int(3).get::<isize>(), Ok(3));
+        assert_eq!(Variant::Int(-3).get::<usize>().ok(), None);
+        assert_eq!(Variant::UIntPtr(1) + Variant::Int(2), Ok(Variant::I64(3)));
+        assert_eq!(7usize.to_variant(), Variant::UIntPtr(7));
+    }
+
+    #[test]
+    fn com_date() {
+        use crate::com_types::date::ComDate;
+
+        let at = |y, m, d, h, min, s, ms| {
+            NaiveDate::from_ymd_opt(y, m, d)
+                .unwrap()
+                .and_hms_milli_opt(h, min, s, ms)
+                .unwrap()
+        };
+        let cases = [
+            (0.0, at(1899, 12, 30, 0, 0, 0, 0)),
+            (2.5, at(1900, 1, 1, 12, 0, 0, 0)),
+            (-1.25, at(1899, 12, 29, 6, 0, 0, 0)),
+            (-2.75, at(1899, 12, 28, 18, 0, 0, 0)),
+            (-657434.0, at(100, 1, 1, 0, 0, 0, 0)),
+            (-657434.5, at(100, 1, 1, 12, 0, 0, 0)),
+            (45352.5, at(2024, 3, 1, 12, 0, 0, 0)),
+        ];
+        for (value, date) in cases {
+            assert_eq!(NaiveDateTime::try_from(ComDate(value)), Ok(date));
+            assert_eq!(ComDate::try_from(date), Ok(ComDate(value)));
+        }
+        assert_eq!(
+            NaiveDateTime::try_from(ComDate(-0.5)),
+            Ok(at(1899, 12, 30, 12, 0, 0, 0))
+        );
+
+        // rounding to the millisecond
+        let date = at(2024, 3, 1, 14, 30, 5, 123);
+        let value = ComDate::try_from(date).unwrap();
+        assert_eq!(NaiveDateTime::try_from(value), Ok(date));
+        assert_eq!(
+            ComDate::try_from(date + chrono::Duration::microseconds(600)),
+            ComDate::try_from(date + chrono::Duration::milliseconds(1))
+        );
+        assert_eq!(
+            NaiveDateTime::try_from(ComDate(1.0 - 1e-10)),
+            Ok(at(1899, 12, 31, 0, 0, 0, 0))
+        );
+
+        let last = at(100, 1, 1, 23, 59, 59, 999);
+        let value = ComDate::try_from(last).unwrap();
+        assert!(value.0 < ComDate::MIN.0 && value.0 > -657435.0);
+        assert_eq!(NaiveDateTime::try_from(value), Ok(last));
+
+        let max = at(9999, 12, 31, 23, 59, 59, 999);
+        assert_eq!(NaiveDateTime::try_from(ComDate::MAX), Ok(max));
+        assert_eq!(ComDate::try_from(max), Ok(ComDate::MAX));
+
+        let overflow = Err(VariantConversionError::Overflow(VariantType::VT_DATE));
+        assert_eq!(NaiveDateTime::try_from(ComDate(-657435.0)), overflow);
+        assert_eq!(NaiveDateTime::try_from(ComDate(2958466.0)), overflow);
+        assert_eq!(NaiveDateTime::try_from(ComDate(f64::NAN)), overflow);
+        assert_eq!(
+            ComDate::try_from(at(99, 12, 31, 23, 59, 59, 0)).err(),
+            Some(VariantConversionError::Overflow(VariantType::VT_DATE))
+        );
+        assert_eq!(
+            TryInto::<VARIANT>::try_into(Variant::Date(at(10000, 1, 1, 0, 0, 0, 0))).err(),
+            Some(VariantConversionError::Overflow(VariantType::VT_DATE))
+        );
+    }
+
+    #[test]
+    fn currency() {
+        use crate::com_types::currency::{ComCurrency, Currency};
+        use rust_decimal::RoundingStrategy;
+
+        let overflow = Some(VariantConversionError::Overflow(VariantType::VT_CY));
+        assert_eq!(ComCurrency::try_from(dec!(1.23445)), Ok(ComCurrency(12344)));
+        assert_eq!(
+            ComCurrency::from_decimal(dec!(1.23445), RoundingStrategy::MidpointAwayFromZero),
+            Ok(ComCurrency(12345))
+        );
+        assert_eq!(ComCurrency::try_from(Currency::MAX), Ok(ComCurrency::MAX));
+        assert_eq!(ComCurrency::try_from(Currency::MIN), Ok(ComCurrency::MIN));
+        assert_eq!(ComCurrency::try_from(dec!(1e15)).err(), overflow);
+        assert_eq!(
+            TryInto::<VARIANT>::try_into(Variant::Currency(dec!(1e15).into())).err(),
+            overflow
+        );
+
+        let cy = |d: Decimal| Currency(d);
+        assert_eq!(cy(dec!(1.5)) + cy(dec!(2.25)), Ok(cy(dec!(3.75))));
+        assert_eq!(cy(dec!(1.5)) - cy(dec!(2.25)), Ok(cy(dec!(-0.75))));
+        assert_eq!(cy(dec!(1.0001)) * cy(dec!(0.5)), Ok(cy(dec!(0.5000))));
+        assert_eq!(cy(dec!(1.0003)) * cy(dec!(0.5)), Ok(cy(dec!(0.5002))));
+        assert_eq!(cy(dec!(1)) / cy(dec!(3)), Ok(cy(dec!(0.3333))));
+        assert_eq!(
+            cy(dec!(1)) / cy(dec!(0)),
+            Err(VariantConversionError::DivisionByZero)
+        );
+        assert_eq!((Currency::MAX + cy(dec!(0.0001))).err(), overflow);
+        assert_eq!((Currency::MAX * cy(dec!(2))).err(), overflow);
+        assert_eq!(-cy(dec!(1.5)), Ok(cy(dec!(-1.5))));
+        assert_eq!((-Currency::MIN).err(), overflow);
+        assert_eq!(cy(dec!(-1.5)).abs(), Ok(cy(dec!(1.5))));
+        assert_eq!(cy(dec!(2.5)).round(0), Ok(cy(dec!(2))));
+        assert_eq!(cy(dec!(3.5)).round(0), Ok(cy(dec!(4))));
+        assert_eq!(cy(dec!(1.2345)).round(2), Ok(cy(dec!(1.23))));
+        assert_eq!(cy(dec!(-1.75)).fix(), cy(dec!(-1)));
+    }
+
+    #[test]
+    fn com_decimal() {
+        use crate::com_types::decimal::ComDecimal;
+        use std::collections::HashSet;
+
+        let d = |d: Decimal| ComDecimal::from(d);
+        assert_eq!(d(dec!(1.0)), d(dec!(1.00)));
+        assert!(d(dec!(-2)) < d(dec!(1.5)));
+        assert_eq!(
+            HashSet::from([d(dec!(1.0)), d(dec!(1.00))]).len(),
+            1,
+            "equal values have the same hash"
+        );
+
+        assert_eq!(d(dec!(1.5)) + d(dec!(2.25)), Ok(d(dec!(3.75))));
+        assert_eq!(d(dec!(1.5)) - d(dec!(2.25)), Ok(d(dec!(-0.75))));
+        assert_eq!(d(dec!(1.5)) * d(dec!(-2)), Ok(d(dec!(-3))));
+        assert_eq!(d(dec!(1)) / d(dec!(4)), Ok(d(dec!(0.25))));
+        assert_eq!(
+            d(dec!(1)) / d(dec!(0)),
+            Err(VariantConversionError::DivisionByZero)
+        );
+        assert_eq!(
+            (d(Decimal::MAX) + d(dec!(1))).err(),
+            Some(VariantConversionError::Overflow(VariantType::VT_DECIMAL))
+        );
+        assert_eq!(d(dec!(2.5)).round(0), Ok(d(dec!(2))));
+        assert_eq!(d(dec!(1.235)).round(2), Ok(d(dec!(1.24))));
+        assert_eq!(d(dec!(-1.5)).fix(), Ok(d(dec!(-1))));
+        assert_eq!(d(dec!(-1.5)).int(), Ok(d(dec!(-2))));
+
+        let mut scale = d(dec!(1));
+        scale.0.Anonymous1.Anonymous.scale = 29;
+        let mut sign = d(dec!(1));
+        sign.0.Anonymous1.Anonymous.sign = 0x01;
+        for invalid in [scale, sign] {
+            assert_eq!(
+                Decimal::try_from(invalid),
+                Err(VariantConversionError::InvalidDecimal)
+            );
+            assert_eq!(
+                (invalid + d(dec!(1))).err(),
+                Some(VariantConversionError::InvalidDecimal)
+            );
+            assert_ne!(invalid, d(dec!(1)));
+            assert!(invalid > d(dec!(1)));
+
+            let mut v = VARIANT::default();
+            v.Anonymous.decVal = invalid.0;
+            assert_eq!(
+                TryInto::<Variant>::try_into(v).err(),
+                Some(VariantConversionError::InvalidDecimal)
+            );
+        }
+    }
+
+    #[test]
+    fn var_type() {
+        use crate::VarType;
+
+        let vt = VarType::new(VariantType::VT_I4).array();
+        assert_eq!(vt.to_string(), "VT_ARRAY|VT_I4");
+        assert_eq!("VT_ARRAY|VT_I4".parse(), Ok(vt));
+        assert_eq!("VT_I4 | VT_ARRAY".parse(), Ok(vt));
+        assert_eq!(VARENUM::from(vt), VARENUM(VariantType::VT_I4.array()));
+        assert_eq!(
+            VarType::try_from(VARENUM(0x8000 | VT_BYREF | VariantType::VT_BSTR as u16))
+                .map(|vt| vt.to_string()),
+            Ok("VT_BYREF|VT_RESERVED|VT_BSTR".to_string())
+        );
+        assert_eq!(
+            VarType::try_from(VARENUM(VT_ARRAY | 15)),
+            Err(VariantConversionError::UnknownType(VARENUM(VT_ARRAY | 15)))
+        );
+        for invalid in ["", "VT_ARRAY", "VT_I4|VT_I2", "VT_FOO"] {
+            assert!(invalid.parse::<VarType>().is_err());
+        }
+
+        let mut value = 5;
+        assert_eq!(
+            Variant::I32Ref(&mut value).var_type(),
+            Ok(VarType::new(VariantType::VT_I4).byref())
+        );
+        assert_eq!(Variant::Null.var_type(), Ok(VariantType::VT_NULL.into()));
+        let array = Variant::Array(SafeArray::from_vec(vec![Variant::F64(1.0)]));
+        let vt = array.var_type().unwrap();
+        assert_eq!(vt.to_string(), "VT_ARRAY|VT_VARIANT");
+        let raw = OwnedVariant::try_from(array).unwrap();
+        assert_eq!(raw.vt(), VARENUM::from(vt));
+
+        assert_eq!(
+            VariantConversionError::UnknownType(VARENUM(VT_BYREF | 15)).to_string(),
+            "The specified variant type is unknown: 0x400f."
+        );
+        assert_eq!(
+            VariantConversionError::TypeMismatch(VariantType::VT_DATE).to_string(),
+            "The value cannot be converted to the target type: VT_DATE."
+        );
+    }
+
+    #[test]
+    fn string_and_object_references() {
+        use windows::core::IUnknown;
+        use windows::Win32::System::Com::IDispatch;
+
+        let mut s = BSTR::from("in");
+        let mut cv = variant!(VariantType::VT_BSTR.byref(), pbstrVal, &mut s);
+        match Variant::try_from(&mut cv).unwrap() {
+            Variant::StringRef(r) => {
+                assert_eq!(*r, "in");
+                *r = BSTR::from("out");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(s, "out");
+
+        let mut d: Option<IDispatch> = None;
+        let mut cv = variant!(VariantType::VT_DISPATCH.byref(), ppdispVal, &mut d);
+        assert_eq!(
+            Variant::try_from(&mut cv)
+                .and_then(|v| v.var_type())
+                .map(|vt| vt.to_string()),
+            Ok("VT_BYREF|VT_DISPATCH".to_string())
+        );
+        let mut u: Option<IUnknown> = None;
+        let mut cv = variant!(VariantType::VT_UNKNOWN.byref(), ppunkVal, &mut u);
+        let v = Variant::try_from(&mut cv).unwrap();
+        assert_eq!(v, Variant::UnknownRef(&mut None));
+        assert_eq!(
+            v.change_type(VariantType::VT_UNKNOWN, Default::default()),
+            Ok(Variant::Unknown(None))
+        );
+
+        let raw: VARIANT = Variant::UnknownRef(&mut u).try_into().unwrap();
+        assert_eq!(
+            unsafe { raw.Anonymous.Anonymous.vt },
+            VARENUM(VariantType::VT_UNKNOWN.byref())
+        );
+    }
+
+    #[test]
+    fn com_bool() {
+        use crate::com_types::bool::ComBool;
+
+        // written by a server as 1 rather than -1
+        let mut value = VARIANT_BOOL(1);
+        let mut cv = variant!(VariantType::VT_BOOL.byref(), pboolVal, &mut value);
+        match Variant::try_from(&mut cv).unwrap() {
+            Variant::BoolRef(b) => {
+                assert!(b.get());
+                assert!(!b.is_canonical());
+                assert_eq!(b.get_strict(), Err(VariantConversionError::InvalidBool(1)));
+                assert_eq!(*b, ComBool::TRUE);
+                b.set(false);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(value, VARIANT_BOOL(0));
+
+        let mut b = ComBool::FALSE;
+        assert_eq!(
+            Variant::BoolRef(&mut b).change_type(VariantType::VT_I2, Default::default()),
+            Ok(Variant::I16(0))
+        );
+        let raw: VARIANT = Variant::BoolRef(&mut b).try_into().unwrap();
+        assert_eq!(
+            unsafe { raw.Anonymous.Anonymous.vt },
+            VARENUM(VariantType::VT_BOOL.byref())
+        );
+        assert_eq!(ComBool::TRUE.get_strict(), Ok(true));
+    }
+
+    #[test]
+    fn null_reference() {
+        use crate::VarType;
+
+        let mut variants = [
+            variant!(VariantType::VT_BOOL.byref()),
+            variant!(VariantType::VT_I4.byref()),
+            variant!(VariantType::VT_CY.byref()),
+            variant!(VariantType::VT_DATE.byref()),
+            variant!(VariantType::VT_BSTR.byref()),
+            variant!(VariantType::VT_ERROR.byref()),
+            variant!(VariantType::VT_VARIANT.byref()),
+            variant!(VariantType::VT_UNKNOWN.byref()),
+            variant!(VariantType::VT_I2.array() | VT_BYREF),
+        ];
+        for cv in &mut variants {
+            let vt = VarType::try_from(unsafe { cv.Anonymous.Anonymous.vt }).unwrap();
+            assert_eq!(
+                Variant::try_from(cv),
+                Err(VariantConversionError::NullPointer(vt))
+            );
+        }
+
+        assert_eq!(
+            Variant::try_from(&mut variant!(VariantType::VT_NULL.byref())),
+            Err(VariantConversionError::InvalidReference(
+                VarType::new(VariantType::VT_NULL).byref()
+            ))
+        );
+        assert_eq!(
+            Variant::try_from(&mut variant!(VariantType::VT_PTR.byref()))
+                .map_err(|e| e.to_string()),
+            Err(
+                "The specified type can only be used in a TYPEDESC structure: VT_BYREF|VT_PTR."
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn raw_variant() {
+        use crate::convert::DecodeMode;
+
+        for vt in [
+            VariantType::VT_PTR as u16,
+            VariantType::VT_FILETIME as u16,
+            15,
+            VT_ARRAY | 15,
+            VariantType::VT_LPSTR.array(),
+        ] {
+            // arrays are freed on drop, so only other types get a payload
+            let payload = if vt & VT_ARRAY == 0 {
+                0x1234_5678_9abc
+            } else {
+                0
+            };
+            let cv = variant!(vt, llVal, payload);
+            assert!(Variant::decode(cv.clone(), DecodeMode::Strict).is_err());
+            let raw = Variant::decode(cv, DecodeMode::Lenient).unwrap();
+            assert_eq!(
+                raw.format(&Default::default()),
+                Err(VariantConversionError::RawValue(VARENUM(vt)))
+            );
+            let back = OwnedVariant::try_from(raw).unwrap();
+            assert_eq!(back.vt(), VARENUM(vt));
+            assert_eq!(
+                unsafe { back.as_raw().Anonymous.Anonymous.Anonymous.llVal },
+                payload
+            );
+        }
+
+        let mut cv = variant!(VariantType::VT_PTR.byref(), llVal, 1);
+        assert!(matches!(
+            Variant::decode_mut(&mut cv, DecodeMode::Lenient),
+            Ok(Variant::Raw(_))
+        ));
+        assert_eq!(
+            unsafe { cv.Anonymous.Anonymous.vt },
+            VARENUM(VariantType::VT_EMPTY as u16)
+        );
+
+        // both paths keep unsupported by-reference types
+        for vt in [
+            VariantType::VT_PTR.byref(),
+            VariantType::VT_RECORD.byref(),
+            VariantType::VT_INT_PTR.byref(),
+        ] {
+            let raw = Variant::decode(variant!(vt, llVal, 1), DecodeMode::Lenient).unwrap();
+            assert_eq!(OwnedVariant::try_from(raw).unwrap().vt(), VARENUM(vt));
+            let mut cv = variant!(vt, llVal, 1);
+            let raw = Variant::decode_mut(&mut cv, DecodeMode::Lenient).unwrap();
+            assert_eq!(OwnedVariant::try_from(raw).unwrap().vt(), VARENUM(vt));
+        }
+        assert_eq!(
+            Variant::decode(variant!(VariantType::VT_I4.byref()), DecodeMode::Lenient),
+            Err(VariantConversionError::BorrowedReference(VARENUM(
+                VariantType::VT_I4.byref()
+            )))
+        );
+
+        // lenient decoding keeps exactly the types that strict decoding rejects
+        for base in 0..=VariantType::VT_VERSIONED_STREAM as u16 + 1 {
+            // records without an IRecordInfo can't be cleared
+            if base == VariantType::VT_RECORD as u16 {
+                continue;
+            }
+            for flags in [0, VT_BYREF, VT_ARRAY, VT_ARRAY | VT_BYREF] {
+                use VariantConversionError::*;
+                let vt = base | flags;
+                let mut cv = variant!(vt);
+                let unsupported = matches!(
+                    Variant::decode_mut(&mut cv, DecodeMode::Strict),
+                    Err(UnknownType(_)
+                        | InvalidDirect(_)
+                        | InvalidReference(_)
+                        | TypeDescOnly(_)
+                        | PropVariantOnly(_)
+                        | InvalidArrayType(_))
+                );
+                let mut cv = variant!(vt);
+                let lenient = Variant::decode_mut(&mut cv, DecodeMode::Lenient);
+                assert_eq!(
+                    matches!(lenient, Ok(Variant::Raw(_))),
+                    unsupported,
+                    "{vt:#x}"
+                );
+                let lenient = Variant::decode(variant!(vt), DecodeMode::Lenient);
+                assert_eq!(
+                    matches!(lenient, Ok(Variant::Raw(_))),
+                    unsupported,
+                    "{vt:#x}"
+                );
+            }
+        }
+
+        // supported types and value errors are unaffected
+        let cv: VARIANT = Variant::I32(5).try_into().unwrap();
+        assert_eq!(
+            Variant::decode(cv, DecodeMode::Lenient),
+            Ok(Variant::I32(5))
+        );
+        let mut cv = variant!(VariantType::VT_I4.byref());
+        assert_eq!(
+            Variant::decode_mut(&mut cv, DecodeMode::Lenient),
+            Err(VariantConversionError::NullPointer(
+                crate::VarType::new(VariantType::VT_I4).byref()
+            ))
+        );
+    }
+
+    #[test]
+    fn try_clone() {
+        use crate::VarType;
+
+        let value = Variant::from(vec!["a", "b"]);
+        assert_eq!(value.try_clone(), Ok(value.clone()));
+
+        let mut i = 5i32;
+        let reference = Variant::I32Ref(&mut i);
+        assert_eq!(
+            reference.try_clone(),
+            Err(VariantConversionError::ReferenceClone(
+                VarType::new(VariantType::VT_I4).byref()
+            ))
+        );
+        assert_eq!(reference.deref_clone(), Ok(Variant::I32(5)));
+
+        // a VT_VARIANT reference to a VT_I4 reference
+        let mut inner = variant!(VariantType::VT_I4.byref(), plVal, &mut i);
+        let mut outer = variant!(VariantType::VT_VARIANT.byref(), pvarVal, &mut inner);
+        let nested = Variant::try_from(&mut outer).unwrap();
+        assert!(nested.try_clone().is_err());
+        assert_eq!(nested.deref_clone(), Ok(Variant::I32(5)));
+
+        // raw elements and fields are reported rather than cloned
+        let raw = || {
+            let vt = VariantType::VT_PTR as u16;
+            Variant::decode(variant!(vt), crate::convert::DecodeMode::Lenient).unwrap()
+        };
+        let raw_error = Err(VariantConversionError::RawValue(VARENUM(
+            VariantType::VT_PTR as u16,
+        )));
+        let array = Variant::Array(SafeArray::from_vec(vec![Variant::I32(1), raw()]));
+        assert_eq!(array.try_clone(), raw_error);
+        let rec = Variant::Record(Record::new(
+            RecordType::new("Point", GUID::zeroed()),
+            vec![("X".into(), raw())],
+        ));
+        assert_eq!(rec.try_clone(), raw_error);
+
+        let mut s = BSTR::from("text");
+        assert_eq!(
+            Variant::StringRef(&mut s).deref_clone(),
+            Ok(Variant::from("text"))
+        );
+    }
 }
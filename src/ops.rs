@@ -0,0 +1,297 @@
+//! VB-compatible arithmetic on [`Variant`] values, following the rules of `VarAdd`, `VarSub`,
+//! `VarMul`, `VarDiv`, `VarMod`, `VarNeg` and `VarPow`
+
+use crate::coerce::{
+    dereference, to_decimal, to_float, to_int, to_number, to_type, value_property, Num,
+};
+use crate::convert::VariantConversionError;
+use crate::sys::BSTR;
+use crate::{Variant, VariantType};
+use std::ops::{Add, Div, Mul, Neg, Rem, Sub};
+
+/// Type of the result of an operation, from narrowest to widest
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Rank {
+    U8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    Date,
+    Currency,
+    Decimal,
+}
+
+impl Rank {
+    fn vt(self) -> VariantType {
+        match self {
+            Rank::U8 => VariantType::VT_UI1,
+            Rank::I16 => VariantType::VT_I2,
+            Rank::I32 => VariantType::VT_I4,
+            Rank::I64 => VariantType::VT_I8,
+            Rank::F32 => VariantType::VT_R4,
+            Rank::F64 => VariantType::VT_R8,
+            Rank::Date => VariantType::VT_DATE,
+            Rank::Currency => VariantType::VT_CY,
+            Rank::Decimal => VariantType::VT_DECIMAL,
+        }
+    }
+
+    /// Rank of an operand, or `None` for `Empty`, which takes the type of the other operand
+    fn of(value: &Variant<'static>) -> Result<Option<Self>, VariantConversionError> {
+        Ok(Some(match value {
+            Variant::Empty => return Ok(None),
+            Variant::U8(_) => Rank::U8,
+            Variant::Bool(_) | Variant::I16(_) => Rank::I16,
+            Variant::I8(_) | Variant::U16(_) | Variant::I32(_) | Variant::Int(_) => Rank::I32,
+            Variant::U32(_) | Variant::UInt(_) | Variant::I64(_) | Variant::U64(_) => Rank::I64,
+            Variant::IntPtr(_) | Variant::UIntPtr(_) => Rank::I64,
+            Variant::F32(_) => Rank::F32,
+            Variant::F64(_) | Variant::String(_) => Rank::F64,
+            Variant::Date(_) => Rank::Date,
+            Variant::Currency(_) => Rank::Currency,
+            Variant::Decimal(_) => Rank::Decimal,
+            _ => return Err(VariantConversionError::TypeMismatch(VariantType::VT_R8)),
+        }))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+/// Dereferences an operand and reads the default value of objects
+fn operand(value: Variant<'_>) -> Result<Variant<'static>, VariantConversionError> {
+    match dereference(value)? {
+        Variant::Dispatch(Some(obj)) => value_property(&obj),
+        value => Ok(value),
+    }
+}
+
+fn result_rank(op: Op, left: Option<Rank>, right: Option<Rank>) -> Rank {
+    use Rank::*;
+    let rank = left.max(right).unwrap_or(I16);
+    let long = |r| matches!(r, Some(I32 | I64));
+    let float = |r| matches!(r, Some(F32 | F64));
+    match op {
+        Op::Add | Op::Sub | Op::Mul if rank == F32 && (long(left) || long(right)) => F64,
+        Op::Sub if left == Some(Date) && right == Some(Date) => F64,
+        Op::Mul if rank == Currency && (float(left) || float(right)) => F64,
+        Op::Mul if rank == Date => F64,
+        Op::Div => match rank {
+            Decimal => Decimal,
+            U8 | I16 | F32 if !long(left) && !long(right) => F32,
+            _ => F64,
+        },
+        Op::Rem => match rank {
+            U8 | I16 | I64 => rank,
+            _ => I32,
+        },
+        _ => rank,
+    }
+}
+
+/// Stores an integer result, promoting it to a wider type on overflow like VB does
+fn fit(value: i128, rank: Rank) -> Result<Variant<'static>, VariantConversionError> {
+    Ok(match rank {
+        Rank::U8 => match u8::try_from(value) {
+            Ok(v) => Variant::U8(v),
+            Err(_) => return fit(value, Rank::I16),
+        },
+        Rank::I16 => match i16::try_from(value) {
+            Ok(v) => Variant::I16(v),
+            Err(_) => return fit(value, Rank::I32),
+        },
+        Rank::I32 => match i32::try_from(value) {
+            Ok(v) => Variant::I32(v),
+            Err(_) => Variant::F64(value as f64),
+        },
+        _ => Variant::I64(
+            value
+                .try_into()
+                .map_err(|_| VariantConversionError::Overflow(VariantType::VT_I8))?,
+        ),
+    })
+}
+
+fn binary(
+    op: Op,
+    left: Variant<'_>,
+    right: Variant<'_>,
+) -> Result<Variant<'static>, VariantConversionError> {
+    let (left, right) = (operand(left)?, operand(right)?);
+    match (&left, &right) {
+        (Variant::Null, _) | (_, Variant::Null) => return Ok(Variant::Null),
+        (Variant::String(a), Variant::String(b)) if op == Op::Add => {
+            let joined = [a.as_wide(), b.as_wide()].concat();
+            return Ok(Variant::String(BSTR::from_wide(&joined)?));
+        }
+        _ => {}
+    }
+
+    let rank = result_rank(op, Rank::of(&left)?, Rank::of(&right)?);
+    let vt = rank.vt();
+    let overflow = VariantConversionError::Overflow(vt);
+    let (a, b) = (to_number(left, vt)?, to_number(right, vt)?);
+    match rank {
+        Rank::U8 | Rank::I16 | Rank::I32 | Rank::I64 => {
+            let (x, y) = (to_int(a, vt)?, to_int(b, vt)?);
+            match op {
+                Op::Add => fit(x + y, rank),
+                Op::Sub => fit(x - y, rank),
+                Op::Mul => fit(x * y, rank),
+                Op::Rem => {
+                    // operands must fit in the result type, which can't overflow then
+                    fit_exact(x, rank)?;
+                    fit_exact(y, rank)?;
+                    if y == 0 {
+                        return Err(VariantConversionError::DivisionByZero);
+                    }
+                    fit(x % y, rank)
+                }
+                Op::Div => unreachable!("division always gives a floating-point result"),
+            }
+        }
+        Rank::F32 => {
+            let (x, y) = (to_float(a) as f32, to_float(b) as f32);
+            let res = match op {
+                Op::Add => x + y,
+                Op::Sub => x - y,
+                Op::Mul => x * y,
+                Op::Div => divide(x as f64, y as f64, vt)? as f32,
+                Op::Rem => unreachable!("modulo always gives an integer result"),
+            };
+            if !res.is_finite() {
+                return Err(overflow);
+            }
+            Ok(Variant::F32(res))
+        }
+        Rank::F64 | Rank::Date => {
+            let (x, y) = (to_float(a), to_float(b));
+            let res = match op {
+                Op::Add => x + y,
+                Op::Sub => x - y,
+                Op::Mul => x * y,
+                Op::Div => divide(x, y, vt)?,
+                Op::Rem => unreachable!("modulo always gives an integer result"),
+            };
+            if !res.is_finite() {
+                return Err(overflow);
+            }
+            to_type(Num::Float(res), vt)
+        }
+        Rank::Currency | Rank::Decimal => {
+            let (x, y) = (to_decimal(a, vt)?, to_decimal(b, vt)?);
+            let res = match op {
+                Op::Add => x.checked_add(y),
+                Op::Sub => x.checked_sub(y),
+                Op::Mul => x.checked_mul(y),
+                Op::Div if y.is_zero() => {
+                    return Err(if x.is_zero() {
+                        overflow
+                    } else {
+                        VariantConversionError::DivisionByZero
+                    })
+                }
+                Op::Div => x.checked_div(y),
+                Op::Rem => unreachable!("modulo always gives an integer result"),
+            };
+            to_type(Num::Dec(res.ok_or(overflow)?), vt)
+        }
+    }
+}
+
+fn fit_exact(value: i128, rank: Rank) -> Result<(), VariantConversionError> {
+    let fits = match rank {
+        Rank::U8 => u8::try_from(value).is_ok(),
+        Rank::I16 => i16::try_from(value).is_ok(),
+        Rank::I32 => i32::try_from(value).is_ok(),
+        _ => i64::try_from(value).is_ok(),
+    };
+    if fits {
+        Ok(())
+    } else {
+        Err(VariantConversionError::Overflow(rank.vt()))
+    }
+}
+
+/// Divides like VB, where `0 / 0` overflows and other divisions by zero are errors
+fn divide(x: f64, y: f64, vt: VariantType) -> Result<f64, VariantConversionError> {
+    if y != 0.0 {
+        Ok(x / y)
+    } else if x == 0.0 {
+        Err(VariantConversionError::Overflow(vt))
+    } else {
+        Err(VariantConversionError::DivisionByZero)
+    }
+}
+
+macro_rules! binary_op {
+    ( $($trait:ident $method:ident $op:ident),* ) => {
+        $(
+            impl<'a, 'b> $trait<Variant<'b>> for Variant<'a> {
+                type Output = Result<Variant<'static>, VariantConversionError>;
+
+                fn $method(self, rhs: Variant<'b>) -> Self::Output {
+                    binary(Op::$op, self, rhs)
+                }
+            }
+        )*
+    };
+}
+
+binary_op! {
+    Add add Add,
+    Sub sub Sub,
+    Mul mul Mul,
+    Div div Div,
+    Rem rem Rem
+}
+
+impl<'a> Neg for Variant<'a> {
+    type Output = Result<Variant<'static>, VariantConversionError>;
+
+    fn neg(self) -> Self::Output {
+        let value = operand(self)?;
+        if matches!(value, Variant::Null) {
+            return Ok(Variant::Null);
+        }
+        let rank = match Rank::of(&value)? {
+            None | Some(Rank::U8) => Rank::I16,
+            Some(rank) => rank,
+        };
+        let vt = rank.vt();
+        let n = to_number(value, vt)?;
+        match rank {
+            Rank::I16 | Rank::I32 | Rank::I64 => fit(-to_int(n, vt)?, rank),
+            Rank::F32 => Ok(Variant::F32(-(to_float(n) as f32))),
+            Rank::F64 | Rank::Date => to_type(Num::Float(-to_float(n)), vt),
+            _ => to_type(Num::Dec(-to_decimal(n, vt)?), vt),
+        }
+    }
+}
+
+impl<'a> Variant<'a> {
+    /// Raises the value to a power, like the `^` operator in VB. The result is always a `Double`,
+    /// or `Null` if either operand is `Null`.
+    pub fn pow(self, exponent: Variant<'_>) -> Result<Variant<'static>, VariantConversionError> {
+        let vt = VariantType::VT_R8;
+        let (base, exponent) = (operand(self)?, operand(exponent)?);
+        if matches!(base, Variant::Null) || matches!(exponent, Variant::Null) {
+            return Ok(Variant::Null);
+        }
+        Rank::of(&base)?;
+        Rank::of(&exponent)?;
+        let res = to_float(to_number(base, vt)?).powf(to_float(to_number(exponent, vt)?));
+        if !res.is_finite() {
+            return Err(VariantConversionError::Overflow(vt));
+        }
+        Ok(Variant::F64(res))
+    }
+}
@@ -0,0 +1,112 @@
+//! Owned [`VARIANT`] with automatic cleanup
+
+use crate::convert::VariantConversionError;
+use crate::sys::{VariantClear, VARIANT};
+use crate::{Variant, VT_BYREF};
+use std::fmt::Debug;
+use std::mem::{size_of, ManuallyDrop};
+use windows::Win32::System::Variant::VARENUM;
+
+/// Owned [`VARIANT`], released with `VariantClear` when dropped.
+///
+/// BSTRs, interface pointers, arrays and records held by the value are freed on drop, while
+/// memory referenced by `VT_BYREF` values is left untouched, like `VariantClear` does.
+#[repr(transparent)]
+pub struct OwnedVariant(VARIANT);
+
+impl OwnedVariant {
+    /// Creates a `VT_EMPTY` variant
+    pub fn new() -> Self {
+        OwnedVariant(VARIANT::default())
+    }
+
+    /// Takes ownership of a raw [`VARIANT`].
+    ///
+    /// # Safety
+    /// `var` must be a valid [`VARIANT`] that isn't owned by anything else.
+    pub unsafe fn from_raw(var: VARIANT) -> Self {
+        OwnedVariant(var)
+    }
+
+    /// Releases ownership of the [`VARIANT`]. The caller becomes responsible for clearing it.
+    pub fn into_raw(self) -> VARIANT {
+        let this = ManuallyDrop::new(self);
+        unsafe { std::ptr::read(&this.0) }
+    }
+
+    pub fn as_raw(&self) -> &VARIANT {
+        &self.0
+    }
+
+    pub fn as_ptr(&self) -> *const VARIANT {
+        &self.0
+    }
+
+    /// Gets a pointer suitable for an `[out]` or `[in, out]` parameter. The current value is
+    /// not cleared, so callees that overwrite it without clearing it first will leak it; call
+    /// [`OwnedVariant::clear`] beforehand in that case.
+    pub fn as_mut_ptr(&mut self) -> *mut VARIANT {
+        &mut self.0
+    }
+
+    pub fn vt(&self) -> VARENUM {
+        unsafe { self.0.Anonymous.Anonymous.vt }
+    }
+
+    /// Frees the value and resets the variant to `VT_EMPTY`
+    pub fn clear(&mut self) {
+        unsafe {
+            let _ = VariantClear(&mut self.0);
+        }
+    }
+}
+
+impl Default for OwnedVariant {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for OwnedVariant {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Compares the bits of the two [`VARIANT`]s
+impl PartialEq for OwnedVariant {
+    fn eq(&self, other: &Self) -> bool {
+        fn bytes(var: &VARIANT) -> &[u8] {
+            unsafe {
+                std::slice::from_raw_parts(var as *const VARIANT as *const u8, size_of::<VARIANT>())
+            }
+        }
+        bytes(&self.0) == bytes(&other.0)
+    }
+}
+
+impl Debug for OwnedVariant {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "OwnedVariant({:#06x})", self.vt().0)
+    }
+}
+
+impl<'a> TryFrom<Variant<'a>> for OwnedVariant {
+    type Error = VariantConversionError;
+
+    fn try_from(value: Variant<'a>) -> Result<Self, VariantConversionError> {
+        Ok(OwnedVariant(value.try_into()?))
+    }
+}
+
+impl TryFrom<OwnedVariant> for Variant<'static> {
+    type Error = VariantConversionError;
+
+    /// By-reference values are rejected like for [`VARIANT`], and the variant is then cleared.
+    fn try_from(value: OwnedVariant) -> Result<Self, VariantConversionError> {
+        if value.vt().0 & VT_BYREF != 0 {
+            return Err(VariantConversionError::BorrowedReference(value.vt()));
+        }
+        value.into_raw().try_into()
+    }
+}
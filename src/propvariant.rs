@@ -0,0 +1,417 @@
+//! Rust wrapper for the [`PROPVARIANT`] type, the superset of [`VARIANT`] used by structured
+//! storage and the property system
+
+use crate::com_types::filetime::ComFileTime;
+use crate::convert::VariantConversionError;
+use crate::sys::VARIANT;
+use crate::{Variant, VariantType, VT_ARRAY, VT_BYREF, VT_VECTOR};
+use chrono::NaiveDateTime;
+use std::ffi::{c_char, CStr, CString};
+use std::mem::{size_of, ManuallyDrop};
+use widestring::U16CString;
+use windows::core::{GUID, PSTR, PWSTR};
+use windows::Win32::Foundation::E_OUTOFMEMORY;
+use windows::Win32::System::Com::StructuredStorage::{
+    IStorage, PropVariantClear, CAC, CLIPDATA, PROPVARIANT, VERSIONEDSTREAM,
+};
+use windows::Win32::System::Com::{CoTaskMemAlloc, CoTaskMemFree, IStream, BLOB};
+use windows::Win32::System::Variant::VARENUM;
+
+macro_rules! propvariant {
+    ( $type: expr ) => {{
+        let mut var = PROPVARIANT::default();
+        (*var.Anonymous.Anonymous).vt = VARENUM($type as u16);
+        var
+    }};
+
+    ( $type: expr, $field: ident, $val: expr ) => {{
+        let mut var = propvariant!($type);
+        (*var.Anonymous.Anonymous).Anonymous.$field = $val;
+        var
+    }};
+}
+
+/// Clipboard data, as stored in `VT_CF` values
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClipData {
+    /// Clipboard format identifier, as stored in the `ulClipFmt` field of [`CLIPDATA`]
+    pub format: i32,
+    pub data: Vec<u8>,
+}
+
+/// Stream with a version identifier, as stored in `VT_VERSIONED_STREAM` values
+#[derive(Clone, Debug, PartialEq)]
+pub struct VersionedStream {
+    pub version: GUID,
+    pub stream: Option<IStream>,
+}
+
+/// Rust wrapper for the [`PROPVARIANT`] type.
+///
+/// Values of the types shared with [`VARIANT`] are stored as a [`Variant`], and are converted
+/// exactly like it, including by-reference values. Strings and buffers of the other types are
+/// copied out of the [`PROPVARIANT`], so only `Variant` values can borrow.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PropVariant<'a> {
+    Variant(Variant<'a>),
+    /// `VT_LPSTR`, a null-terminated string in the system code page
+    AnsiString(CString),
+    /// `VT_LPWSTR`
+    WideString(U16CString),
+    FileTime(NaiveDateTime),
+    Blob(Vec<u8>),
+    Stream(Option<IStream>),
+    Storage(Option<IStorage>),
+    ClipData(ClipData),
+    Clsid(GUID),
+    VersionedStream(VersionedStream),
+    /// Counted array (`VT_VECTOR`) of values of the given type. `VT_VARIANT` vectors can hold
+    /// values of any type, while other vectors must only hold values of the element type.
+    Vector(VariantType, Vec<PropVariant<'static>>),
+}
+
+impl<'a> From<Variant<'a>> for PropVariant<'a> {
+    fn from(variant: Variant<'a>) -> Self {
+        PropVariant::Variant(variant)
+    }
+}
+
+/// Checks whether a [`PROPVARIANT`] has the same layout and meaning as a [`VARIANT`]
+fn is_variant(vt: VARENUM) -> bool {
+    use VariantType::*;
+    vt.0 & VT_VECTOR == 0
+        && !matches!(
+            VariantType::n(vt.0 & !(VT_ARRAY | VT_BYREF)),
+            Some(
+                VT_LPSTR
+                    | VT_LPWSTR
+                    | VT_FILETIME
+                    | VT_BLOB
+                    | VT_STREAM
+                    | VT_STORAGE
+                    | VT_STREAMED_OBJECT
+                    | VT_STORED_OBJECT
+                    | VT_BLOB_OBJECT
+                    | VT_CF
+                    | VT_CLSID
+                    | VT_VERSIONED_STREAM
+            )
+        )
+}
+
+/// Allocates zeroed memory with `CoTaskMemAlloc`, which is how [`PropVariantClear`] expects
+/// strings and buffers to be allocated. Empty buffers are represented by a null pointer.
+unsafe fn alloc(size: usize) -> Result<*mut u8, VariantConversionError> {
+    if size == 0 {
+        return Ok(std::ptr::null_mut());
+    }
+    let ptr = CoTaskMemAlloc(size) as *mut u8;
+    if ptr.is_null() {
+        return Err(VariantConversionError::ComError(E_OUTOFMEMORY));
+    }
+    ptr.write_bytes(0, size);
+    Ok(ptr)
+}
+
+unsafe fn alloc_copy<T: Copy>(data: &[T]) -> Result<*mut T, VariantConversionError> {
+    let ptr = alloc(std::mem::size_of_val(data))? as *mut T;
+    if !ptr.is_null() {
+        ptr.copy_from_nonoverlapping(data.as_ptr(), data.len());
+    }
+    Ok(ptr)
+}
+
+unsafe fn copy_bytes(ptr: *const u8, len: usize) -> Result<Vec<u8>, VariantConversionError> {
+    if len == 0 {
+        Ok(Vec::new())
+    } else if ptr.is_null() {
+        Err(VariantConversionError::GenericConversionError)
+    } else {
+        Ok(std::slice::from_raw_parts(ptr, len).to_vec())
+    }
+}
+
+/// Gets a pointer to the value of a [`PROPVARIANT`], which all the fields of its union share
+unsafe fn value_ptr(var: &mut PROPVARIANT) -> *mut u8 {
+    &mut (*var.Anonymous.Anonymous).Anonymous as *mut _ as *mut u8
+}
+
+/// How the elements of a vector are stored
+#[derive(Clone, Copy)]
+enum ElementLayout {
+    /// Like the value of a [`PROPVARIANT`] of the element type
+    Value(usize),
+    /// Like the value pointed to by a [`PROPVARIANT`] of the element type
+    Pointer(usize),
+    /// As a whole [`PROPVARIANT`]
+    PropVariant,
+}
+
+impl ElementLayout {
+    fn size(self) -> usize {
+        match self {
+            ElementLayout::Value(size) | ElementLayout::Pointer(size) => size,
+            ElementLayout::PropVariant => size_of::<PROPVARIANT>(),
+        }
+    }
+}
+
+fn element_layout(element_type: VariantType) -> Result<ElementLayout, VariantConversionError> {
+    use VariantType::*;
+    Ok(match element_type {
+        VT_I1 | VT_UI1 => ElementLayout::Value(1),
+        VT_I2 | VT_UI2 | VT_BOOL => ElementLayout::Value(2),
+        VT_I4 | VT_UI4 | VT_R4 | VT_ERROR => ElementLayout::Value(4),
+        VT_I8 | VT_UI8 | VT_R8 | VT_CY | VT_DATE | VT_FILETIME => ElementLayout::Value(8),
+        VT_BSTR | VT_LPSTR | VT_LPWSTR => ElementLayout::Value(size_of::<usize>()),
+        VT_CLSID => ElementLayout::Pointer(size_of::<GUID>()),
+        VT_CF => ElementLayout::Pointer(size_of::<CLIPDATA>()),
+        VT_VARIANT => ElementLayout::PropVariant,
+        _ => return Err(VariantConversionError::InvalidArrayType(element_type)),
+    })
+}
+
+/// Copies the elements of a vector. All the `CA*` structs share the layout of [`CAC`].
+unsafe fn copy_vector(
+    var: &PROPVARIANT,
+    element_type: VariantType,
+) -> Result<PropVariant<'static>, VariantConversionError> {
+    let layout = element_layout(element_type)?;
+    let array = &var.Anonymous.Anonymous.Anonymous.cac;
+    let (count, data) = (array.cElems as usize, array.pElems.0);
+    if count > 0 && data.is_null() {
+        return Err(VariantConversionError::GenericConversionError);
+    }
+    let elements = (0..count)
+        .map(|i| {
+            let ptr = data.add(i * layout.size());
+            match layout {
+                ElementLayout::Value(size) => {
+                    let mut element = propvariant!(element_type);
+                    ptr.copy_to_nonoverlapping(value_ptr(&mut element), size);
+                    PropVariant::try_from(&element)
+                }
+                ElementLayout::Pointer(_) => {
+                    let element = propvariant!(element_type, puuid, ptr as *mut GUID);
+                    PropVariant::try_from(&element)
+                }
+                ElementLayout::PropVariant => PropVariant::try_from(&*(ptr as *const PROPVARIANT)),
+            }
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(PropVariant::Vector(element_type, elements))
+}
+
+/// Allocates a vector and moves the elements into it
+unsafe fn vector_into_raw(
+    element_type: VariantType,
+    elements: Vec<PropVariant<'static>>,
+) -> Result<PROPVARIANT, VariantConversionError> {
+    let layout = element_layout(element_type)?;
+    let data = alloc(elements.len() * layout.size())?;
+    let mut var = propvariant!(
+        element_type.vector(),
+        cac,
+        CAC {
+            cElems: elements.len() as u32,
+            pElems: PSTR(data),
+        }
+    );
+    for (i, element) in elements.into_iter().enumerate() {
+        let ptr = data.add(i * layout.size());
+        let res = PROPVARIANT::try_from(element).and_then(|mut element| {
+            let vt = element.Anonymous.Anonymous.vt.0;
+            match layout {
+                ElementLayout::PropVariant => {
+                    (ptr as *mut PROPVARIANT).write(element);
+                    return Ok(());
+                }
+                _ if vt != element_type as u16 => {
+                    let _ = PropVariantClear(&mut element);
+                    return Err(VariantConversionError::ArrayElementMismatch(element_type));
+                }
+                ElementLayout::Value(size) => {
+                    ptr.copy_from_nonoverlapping(value_ptr(&mut element), size);
+                }
+                ElementLayout::Pointer(size) => {
+                    let value = *(value_ptr(&mut element) as *const *mut u8);
+                    ptr.copy_from_nonoverlapping(value, size);
+                    CoTaskMemFree(Some(value as *const _));
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = res {
+            // elements that weren't written yet are zeroed, which is valid for all types
+            let _ = PropVariantClear(&mut var);
+            return Err(e);
+        }
+    }
+    Ok(var)
+}
+
+impl TryFrom<&PROPVARIANT> for PropVariant<'static> {
+    type Error = VariantConversionError;
+
+    /// Decodes a [`PROPVARIANT`] without taking ownership of its value, like `PropVariantCopy`.
+    /// By-reference values are rejected, since they can only be borrowed from a
+    /// `&mut PROPVARIANT`.
+    fn try_from(var: &PROPVARIANT) -> Result<PropVariant<'static>, VariantConversionError> {
+        use VariantType::*;
+        unsafe {
+            let vt = var.Anonymous.Anonymous.vt;
+            if is_variant(vt) {
+                return Variant::try_from(&*(var as *const PROPVARIANT as *const VARIANT))
+                    .map(PropVariant::Variant);
+            }
+            let val = &var.Anonymous.Anonymous.Anonymous;
+            let element_type =
+                VariantType::n(vt.0 & !VT_VECTOR).ok_or(VariantConversionError::UnknownType(vt))?;
+            if vt.0 & VT_VECTOR != 0 {
+                return copy_vector(var, element_type);
+            }
+            Ok(match element_type {
+                VT_LPSTR if !val.pszVal.is_null() => PropVariant::AnsiString(
+                    CStr::from_ptr(val.pszVal.0 as *const c_char).to_owned(),
+                ),
+                VT_LPWSTR if !val.pwszVal.is_null() => {
+                    PropVariant::WideString(U16CString::from_ptr_str(val.pwszVal.0))
+                }
+                VT_FILETIME => PropVariant::FileTime(ComFileTime(val.filetime).into()),
+                VT_BLOB => {
+                    PropVariant::Blob(copy_bytes(val.blob.pBlobData, val.blob.cbSize as usize)?)
+                }
+                VT_STREAM => PropVariant::Stream((*val.pStream).clone()),
+                VT_STORAGE => PropVariant::Storage((*val.pStorage).clone()),
+                VT_CF if !val.pclipdata.is_null() => {
+                    let clip = &*val.pclipdata;
+                    let len = (clip.cbSize as usize).saturating_sub(size_of::<i32>());
+                    PropVariant::ClipData(ClipData {
+                        format: clip.ulClipFmt,
+                        data: copy_bytes(clip.pClipData, len)?,
+                    })
+                }
+                VT_CLSID if !val.puuid.is_null() => PropVariant::Clsid(*val.puuid),
+                VT_VERSIONED_STREAM if !val.pVersionedStream.is_null() => {
+                    let stream = &*val.pVersionedStream;
+                    PropVariant::VersionedStream(VersionedStream {
+                        version: stream.guidVersion,
+                        stream: (*stream.pStream).clone(),
+                    })
+                }
+                VT_LPSTR | VT_LPWSTR | VT_CF | VT_CLSID | VT_VERSIONED_STREAM => {
+                    return Err(VariantConversionError::GenericConversionError)
+                }
+                _ => return Err(VariantConversionError::Unimplemented(element_type)),
+            })
+        }
+    }
+}
+
+impl TryFrom<PROPVARIANT> for PropVariant<'static> {
+    type Error = VariantConversionError;
+
+    /// Decodes an owned [`PROPVARIANT`]. Values of the types shared with [`VARIANT`] are decoded
+    /// like an owned [`VARIANT`]. Other values are copied, and the [`PROPVARIANT`] is then
+    /// cleared.
+    fn try_from(mut var: PROPVARIANT) -> Result<PropVariant<'static>, VariantConversionError> {
+        unsafe {
+            if is_variant(var.Anonymous.Anonymous.vt) {
+                let var = std::mem::transmute::<PROPVARIANT, VARIANT>(var);
+                return var.try_into().map(PropVariant::Variant);
+            }
+            let res = PropVariant::try_from(&var);
+            let _ = PropVariantClear(&mut var);
+            res
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a mut PROPVARIANT> for PropVariant<'a> {
+    type Error = VariantConversionError;
+
+    /// Decodes a borrowed [`PROPVARIANT`]. Values of the types shared with [`VARIANT`] are
+    /// decoded like a borrowed [`VARIANT`]. Other values are moved out of the [`PROPVARIANT`],
+    /// which is left as `VT_EMPTY`.
+    fn try_from(var: &'a mut PROPVARIANT) -> Result<PropVariant<'a>, VariantConversionError> {
+        unsafe {
+            if is_variant(var.Anonymous.Anonymous.vt) {
+                let var = &mut *(var as *mut PROPVARIANT as *mut VARIANT);
+                return Variant::try_from(var).map(PropVariant::Variant);
+            }
+            std::mem::take(var).try_into()
+        }
+    }
+}
+
+impl<'a> TryFrom<PropVariant<'a>> for PROPVARIANT {
+    type Error = VariantConversionError;
+
+    /// Encodes a value, allocating strings and buffers with `CoTaskMemAlloc`. The caller is
+    /// responsible for clearing the returned [`PROPVARIANT`] with `PropVariantClear`.
+    fn try_from(value: PropVariant<'a>) -> Result<PROPVARIANT, VariantConversionError> {
+        use VariantType::*;
+        unsafe {
+            Ok(match value {
+                PropVariant::Variant(v) => {
+                    let var: VARIANT = v.try_into()?;
+                    std::mem::transmute::<VARIANT, PROPVARIANT>(var)
+                }
+                PropVariant::AnsiString(s) => {
+                    let ptr = alloc_copy(s.as_bytes_with_nul())?;
+                    propvariant!(VT_LPSTR, pszVal, PSTR(ptr))
+                }
+                PropVariant::WideString(s) => {
+                    let ptr = alloc_copy(s.as_slice_with_nul())?;
+                    propvariant!(VT_LPWSTR, pwszVal, PWSTR(ptr))
+                }
+                PropVariant::FileTime(d) => {
+                    propvariant!(VT_FILETIME, filetime, ComFileTime::try_from(d)?.0)
+                }
+                PropVariant::Blob(b) => propvariant!(
+                    VT_BLOB,
+                    blob,
+                    BLOB {
+                        cbSize: b.len() as u32,
+                        pBlobData: alloc_copy(&b)?,
+                    }
+                ),
+                PropVariant::Stream(s) => propvariant!(VT_STREAM, pStream, ManuallyDrop::new(s)),
+                PropVariant::Storage(s) => {
+                    propvariant!(VT_STORAGE, pStorage, ManuallyDrop::new(s))
+                }
+                PropVariant::ClipData(c) => {
+                    let data = alloc_copy(&c.data)?;
+                    let clip = match alloc(size_of::<CLIPDATA>()) {
+                        Ok(clip) => clip as *mut CLIPDATA,
+                        Err(e) => {
+                            CoTaskMemFree(Some(data as *const _));
+                            return Err(e);
+                        }
+                    };
+                    clip.write(CLIPDATA {
+                        cbSize: (c.data.len() + size_of::<i32>()) as u32,
+                        ulClipFmt: c.format,
+                        pClipData: data,
+                    });
+                    propvariant!(VT_CF, pclipdata, clip)
+                }
+                PropVariant::Clsid(g) => {
+                    let ptr = alloc_copy(&[g])?;
+                    propvariant!(VT_CLSID, puuid, ptr)
+                }
+                PropVariant::VersionedStream(s) => {
+                    let ptr = alloc(size_of::<VERSIONEDSTREAM>())? as *mut VERSIONEDSTREAM;
+                    ptr.write(VERSIONEDSTREAM {
+                        guidVersion: s.version,
+                        pStream: ManuallyDrop::new(s.stream),
+                    });
+                    propvariant!(VT_VERSIONED_STREAM, pVersionedStream, ptr)
+                }
+                PropVariant::Vector(element_type, elements) => {
+                    vector_into_raw(element_type, elements)?
+                }
+            })
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! [`serde`] support, enabled by the `serde` feature
+//!
+//! Variants are encoded as an enum whose variant names are those of [`Variant`], so the exact
+//! type of the value survives a round trip. By-reference values, non-null interface pointers and
+//! raw values can't be serialized.
+
+use crate::com_types::currency::Currency;
+use crate::com_types::record::{Record, RecordType};
+use crate::com_types::safearray::{SafeArray, SafeArrayBound};
+use crate::convert::type_name;
+use crate::sys::BSTR;
+use crate::{Variant, VariantType};
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use serde::de::Error as _;
+use serde::ser::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use windows::core::{GUID, HRESULT};
+
+/// Serialized form of a variant, holding arrays and records as `A` and `R`, which are borrowed
+/// when serializing and owned when deserializing
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Variant")]
+enum VariantRepr<A, R> {
+    Empty,
+    Null,
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    Int(i32),
+    UInt(u32),
+    IntPtr(isize),
+    UIntPtr(usize),
+    F32(f32),
+    F64(f64),
+    Currency(Currency),
+    Decimal(Decimal),
+    Date(NaiveDateTime),
+    String(String),
+    /// Null `IDispatch` pointer
+    Dispatch,
+    /// Null `IUnknown` pointer
+    Unknown,
+    Error(i32),
+    Array(A),
+    Record(R),
+}
+
+impl<'a> Serialize for Variant<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let repr = match self {
+            Variant::Empty => VariantRepr::Empty,
+            Variant::Null => VariantRepr::Null,
+            Variant::Bool(b) => VariantRepr::Bool(*b),
+            Variant::I8(i) => VariantRepr::I8(*i),
+            Variant::I16(i) => VariantRepr::I16(*i),
+            Variant::I32(i) => VariantRepr::I32(*i),
+            Variant::I64(i) => VariantRepr::I64(*i),
+            Variant::U8(i) => VariantRepr::U8(*i),
+            Variant::U16(i) => VariantRepr::U16(*i),
+            Variant::U32(i) => VariantRepr::U32(*i),
+            Variant::U64(i) => VariantRepr::U64(*i),
+            Variant::Int(i) => VariantRepr::Int(*i),
+            Variant::UInt(i) => VariantRepr::UInt(*i),
+            Variant::IntPtr(i) => VariantRepr::IntPtr(*i),
+            Variant::UIntPtr(i) => VariantRepr::UIntPtr(*i),
+            Variant::F32(f) => VariantRepr::F32(*f),
+            Variant::F64(f) => VariantRepr::F64(*f),
+            Variant::Currency(c) => VariantRepr::Currency(*c),
+            Variant::Decimal(d) => VariantRepr::Decimal(*d),
+            Variant::Date(d) => VariantRepr::Date(*d),
+            Variant::String(s) => {
+                VariantRepr::String(String::from_utf16(s.as_wide()).map_err(S::Error::custom)?)
+            }
+            Variant::Dispatch(None) => VariantRepr::Dispatch,
+            Variant::Unknown(None) => VariantRepr::Unknown,
+            Variant::Dispatch(Some(_)) | Variant::Unknown(Some(_)) => {
+                return Err(S::Error::custom("interface pointers can't be serialized"))
+            }
+            Variant::Error(e) => VariantRepr::Error(e.0),
+            Variant::Array(a) => VariantRepr::Array(a),
+            Variant::Record(r) => VariantRepr::Record(r),
+            Variant::Raw(var) => {
+                return Err(S::Error::custom(format!(
+                    "raw variants can't be serialized: {}",
+                    type_name(var.vt())
+                )))
+            }
+            _ => {
+                return Err(S::Error::custom(
+                    "by-reference variants can't be serialized",
+                ))
+            }
+        };
+        repr.serialize(serializer)
+    }
+}
+
+impl<'de, 'a> Deserialize<'de> for Variant<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match VariantRepr::deserialize(deserializer)? {
+            VariantRepr::Empty => Variant::Empty,
+            VariantRepr::Null => Variant::Null,
+            VariantRepr::Bool(b) => Variant::Bool(b),
+            VariantRepr::I8(i) => Variant::I8(i),
+            VariantRepr::I16(i) => Variant::I16(i),
+            VariantRepr::I32(i) => Variant::I32(i),
+            VariantRepr::I64(i) => Variant::I64(i),
+            VariantRepr::U8(i) => Variant::U8(i),
+            VariantRepr::U16(i) => Variant::U16(i),
+            VariantRepr::U32(i) => Variant::U32(i),
+            VariantRepr::U64(i) => Variant::U64(i),
+            VariantRepr::Int(i) => Variant::Int(i),
+            VariantRepr::UInt(i) => Variant::UInt(i),
+            VariantRepr::IntPtr(i) => Variant::IntPtr(i),
+            VariantRepr::UIntPtr(i) => Variant::UIntPtr(i),
+            VariantRepr::F32(f) => Variant::F32(f),
+            VariantRepr::F64(f) => Variant::F64(f),
+            VariantRepr::Currency(c) => Variant::Currency(c),
+            VariantRepr::Decimal(d) => Variant::Decimal(d),
+            VariantRepr::Date(d) => Variant::Date(d),
+            VariantRepr::String(s) => Variant::String(BSTR::from(s)),
+            VariantRepr::Dispatch => Variant::Dispatch(None),
+            VariantRepr::Unknown => Variant::Unknown(None),
+            VariantRepr::Error(e) => Variant::Error(HRESULT(e)),
+            VariantRepr::Array(a) => Variant::Array(a),
+            VariantRepr::Record(r) => Variant::Record(r),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "SafeArray")]
+struct SafeArrayRepr<'r> {
+    element_type: VariantType,
+    bounds: Cow<'r, [SafeArrayBound]>,
+    elements: Cow<'r, [Variant<'static>]>,
+}
+
+impl Serialize for SafeArray {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SafeArrayRepr {
+            element_type: self.element_type(),
+            bounds: Cow::Borrowed(self.bounds()),
+            elements: Cow::Borrowed(self.elements()),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Deserialized arrays are validated like with [`SafeArray::new`]
+impl<'de> Deserialize<'de> for SafeArray {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = SafeArrayRepr::deserialize(deserializer)?;
+        SafeArray::new(
+            repr.element_type,
+            repr.bounds.into_owned(),
+            repr.elements.into_owned(),
+        )
+        .map_err(D::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Record")]
+struct RecordRepr<'r> {
+    name: Cow<'r, str>,
+    guid: String,
+    fields: Cow<'r, [(String, Variant<'static>)]>,
+}
+
+/// The [`IRecordInfo`](windows::Win32::System::Ole::IRecordInfo) of the record type isn't
+/// serialized, so deserialized records can't be converted back to a `VARIANT` until it is set.
+impl Serialize for Record {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RecordRepr {
+            name: Cow::Borrowed(&self.record_type.name),
+            guid: format!("{:?}", self.record_type.guid),
+            fields: Cow::Borrowed(&self.fields),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Record {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = RecordRepr::deserialize(deserializer)?;
+        let guid = parse_guid(&repr.guid)
+            .ok_or_else(|| D::Error::custom(format!("invalid GUID {}", repr.guid)))?;
+        Ok(Record::new(
+            RecordType::new(repr.name, guid),
+            repr.fields.into_owned(),
+        ))
+    }
+}
+
+/// Parses a GUID in the `00000000-0000-0000-0000-000000000000` format used by its `Debug` impl
+fn parse_guid(s: &str) -> Option<GUID> {
+    let hex: String = s.chars().filter(|&c| c != '-').collect();
+    if s.len() != 36 || hex.len() != 32 {
+        return None;
+    }
+    u128::from_str_radix(&hex, 16).ok().map(GUID::from_u128)
+}
@@ -0,0 +1,31 @@
+//! Types and functions shared with the OLE Automation runtime
+//!
+//! By default, and always on Windows, these are the definitions of the `windows` crate, which call
+//! into oleaut32. With the `portable` feature on other platforms, they are replaced by
+//! layout-compatible definitions backed by a Rust allocator, so that [`Variant`](crate::Variant)
+//! values can be built, converted, coerced and serialized without oleaut32.
+//!
+//! Interfaces ([`IUnknown`](windows::core::IUnknown), [`IDispatch`](windows::Win32::System::Com::IDispatch),
+//! etc.) and plain data types such as [`VARENUM`](windows::Win32::System::Variant::VARENUM) or
+//! [`SAFEARRAY`](windows::Win32::System::Com::SAFEARRAY) come from the `windows` crate in both
+//! cases.
+
+#[cfg(not(all(feature = "portable", not(windows))))]
+pub use windows::core::BSTR;
+#[cfg(not(all(feature = "portable", not(windows))))]
+pub use windows::Win32::Foundation::{DECIMAL, DECIMAL_0, DECIMAL_0_0, DECIMAL_1, DECIMAL_1_0};
+#[cfg(not(all(feature = "portable", not(windows))))]
+pub use windows::Win32::System::Com::{CY, CY_0};
+#[cfg(not(all(feature = "portable", not(windows))))]
+pub use windows::Win32::System::Ole::{SafeArrayCreate, SafeArrayDestroy};
+#[cfg(not(all(feature = "portable", not(windows))))]
+pub use windows::Win32::System::Variant::{
+    VariantClear, VARIANT, VARIANT_0, VARIANT_0_0, VARIANT_0_0_0, VARIANT_0_0_0_0,
+};
+
+#[cfg(all(feature = "portable", not(windows)))]
+mod portable;
+#[cfg(all(feature = "portable", not(windows)))]
+pub use portable::*;
+#[cfg(all(feature = "win32-stubs", not(windows)))]
+mod stubs;
@@ -0,0 +1,522 @@
+//! Layout-compatible definitions of [`BSTR`], [`VARIANT`], [`DECIMAL`] and [`CY`], and of the
+//! oleaut32 functions used by this crate, for the `portable` feature.
+//!
+//! Strings and arrays are allocated with the Rust allocator, so they can only be freed by the
+//! functions of this module, and not by oleaut32.
+
+#![allow(non_snake_case, non_camel_case_types)]
+
+use crate::convert::VariantConversionError;
+use crate::VariantType;
+use std::alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout};
+use std::ffi::c_void;
+use std::fmt::{Debug, Display};
+use std::mem::{align_of, size_of, ManuallyDrop};
+use std::ptr::null_mut;
+use windows::core::{IUnknown, Interface, HRESULT, PSTR};
+use windows::Win32::Foundation::{DISP_E_ARRAYISLOCKED, E_INVALIDARG, S_OK, VARIANT_BOOL};
+use windows::Win32::System::Com::{
+    IDispatch, ADVANCED_FEATURE_FLAGS, FADF_BSTR, FADF_DISPATCH, FADF_UNKNOWN, FADF_VARIANT,
+    SAFEARRAY, SAFEARRAYBOUND,
+};
+use windows::Win32::System::Ole::IRecordInfo;
+use windows::Win32::System::Variant::{
+    VARENUM, VT_ARRAY, VT_BOOL, VT_BSTR, VT_BYREF, VT_CY, VT_DATE, VT_DECIMAL, VT_DISPATCH,
+    VT_ERROR, VT_I1, VT_I2, VT_I4, VT_I8, VT_INT, VT_R4, VT_R8, VT_RECORD, VT_UI1, VT_UI2, VT_UI4,
+    VT_UI8, VT_UINT, VT_UNKNOWN, VT_VARIANT,
+};
+
+/// Length-prefixed wide string. Like with `SysAllocStringLen`, the length in bytes is stored in
+/// the four bytes before the characters, which are followed by a null character.
+#[repr(transparent)]
+pub struct BSTR(*const u16);
+
+impl BSTR {
+    pub const fn new() -> Self {
+        BSTR(std::ptr::null())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len(&self) -> usize {
+        if self.0.is_null() {
+            0
+        } else {
+            unsafe { (self.0 as *const u32).sub(1).read() as usize / size_of::<u16>() }
+        }
+    }
+
+    pub fn as_wide(&self) -> &[u16] {
+        if self.0.is_null() {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.0, self.len()) }
+    }
+
+    /// Allocates a string, failing if it is longer than a [`BSTR`] can be
+    pub fn from_wide(value: &[u16]) -> Result<Self, VariantConversionError> {
+        if value.is_empty() {
+            return Ok(Self::new());
+        }
+        let bytes = u32::try_from(std::mem::size_of_val(value))
+            .map_err(|_| VariantConversionError::Overflow(VariantType::VT_BSTR))?;
+        let layout = string_layout(value.len());
+        unsafe {
+            let ptr = alloc_zeroed(layout);
+            if ptr.is_null() {
+                handle_alloc_error(layout);
+            }
+            (ptr as *mut u32).write(bytes);
+            let chars = ptr.add(size_of::<u32>()) as *mut u16;
+            chars.copy_from_nonoverlapping(value.as_ptr(), value.len());
+            Ok(BSTR(chars))
+        }
+    }
+
+    /// # Safety
+    /// `raw` must be null or have been returned by [`BSTR::into_raw`].
+    #[doc(hidden)]
+    pub unsafe fn from_raw(raw: *const u16) -> Self {
+        BSTR(raw)
+    }
+
+    #[doc(hidden)]
+    pub fn into_raw(self) -> *const u16 {
+        ManuallyDrop::new(self).0
+    }
+}
+
+/// Prefix, characters and terminating null of a string of `len` characters
+fn string_layout(len: usize) -> Layout {
+    Layout::from_size_align(
+        size_of::<u32>() + (len + 1) * size_of::<u16>(),
+        align_of::<u32>(),
+    )
+    .unwrap()
+}
+
+impl Drop for BSTR {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe {
+                let ptr = (self.0 as *mut u8).sub(size_of::<u32>());
+                dealloc(ptr, string_layout(self.len()));
+            }
+        }
+    }
+}
+
+impl Clone for BSTR {
+    fn clone(&self) -> Self {
+        Self::from_wide(self.as_wide()).unwrap()
+    }
+}
+
+impl Default for BSTR {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<&str> for BSTR {
+    fn from(value: &str) -> Self {
+        let value: Vec<u16> = value.encode_utf16().collect();
+        Self::from_wide(&value).unwrap()
+    }
+}
+
+impl From<String> for BSTR {
+    fn from(value: String) -> Self {
+        value.as_str().into()
+    }
+}
+
+impl From<&String> for BSTR {
+    fn from(value: &String) -> Self {
+        value.as_str().into()
+    }
+}
+
+impl TryFrom<&BSTR> for String {
+    type Error = std::string::FromUtf16Error;
+
+    fn try_from(value: &BSTR) -> Result<Self, Self::Error> {
+        String::from_utf16(value.as_wide())
+    }
+}
+
+impl TryFrom<BSTR> for String {
+    type Error = std::string::FromUtf16Error;
+
+    fn try_from(value: BSTR) -> Result<Self, Self::Error> {
+        String::try_from(&value)
+    }
+}
+
+impl Display for BSTR {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        char::decode_utf16(self.as_wide().iter().copied())
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .try_for_each(|c| write!(f, "{}", c))
+    }
+}
+
+impl Debug for BSTR {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl PartialEq for BSTR {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_wide() == other.as_wide()
+    }
+}
+
+impl Eq for BSTR {}
+
+impl<T: AsRef<str> + ?Sized> PartialEq<T> for BSTR {
+    fn eq(&self, other: &T) -> bool {
+        self.as_wide()
+            .iter()
+            .copied()
+            .eq(other.as_ref().encode_utf16())
+    }
+}
+
+impl PartialEq<BSTR> for &str {
+    fn eq(&self, other: &BSTR) -> bool {
+        other == self
+    }
+}
+
+impl PartialEq<BSTR> for String {
+    fn eq(&self, other: &BSTR) -> bool {
+        other == self
+    }
+}
+
+/// Implements `Clone` as a bitwise copy and `Default` as all zeroes, like the `windows` crate
+macro_rules! plain {
+    ($($name:ident),*) => {
+        $(
+            // some of these types are also `Copy`, others hold interfaces
+            #[allow(clippy::non_canonical_clone_impl)]
+            impl Clone for $name {
+                fn clone(&self) -> Self {
+                    unsafe { std::mem::transmute_copy(self) }
+                }
+            }
+
+            impl Default for $name {
+                fn default() -> Self {
+                    unsafe { std::mem::zeroed() }
+                }
+            }
+        )*
+    };
+}
+
+#[repr(C)]
+#[derive(Copy)]
+pub union CY {
+    pub Anonymous: CY_0,
+    pub int64: i64,
+}
+
+#[repr(C)]
+#[derive(Copy)]
+pub struct CY_0 {
+    pub Lo: u32,
+    pub Hi: i32,
+}
+
+#[repr(C)]
+#[derive(Copy)]
+pub struct DECIMAL {
+    pub wReserved: u16,
+    pub Anonymous1: DECIMAL_0,
+    pub Hi32: u32,
+    pub Anonymous2: DECIMAL_1,
+}
+
+#[repr(C)]
+#[derive(Copy)]
+pub union DECIMAL_0 {
+    pub Anonymous: DECIMAL_0_0,
+    pub signscale: u16,
+}
+
+#[repr(C)]
+#[derive(Copy)]
+pub struct DECIMAL_0_0 {
+    pub scale: u8,
+    pub sign: u8,
+}
+
+#[repr(C)]
+#[derive(Copy)]
+pub union DECIMAL_1 {
+    pub Anonymous: DECIMAL_1_0,
+    pub Lo64: u64,
+}
+
+#[repr(C)]
+#[derive(Copy)]
+pub struct DECIMAL_1_0 {
+    pub Lo32: u32,
+    pub Mid32: u32,
+}
+
+#[repr(C)]
+pub struct VARIANT {
+    pub Anonymous: VARIANT_0,
+}
+
+#[repr(C)]
+pub union VARIANT_0 {
+    pub Anonymous: ManuallyDrop<VARIANT_0_0>,
+    pub decVal: DECIMAL,
+}
+
+#[repr(C)]
+pub struct VARIANT_0_0 {
+    pub vt: VARENUM,
+    pub wReserved1: u16,
+    pub wReserved2: u16,
+    pub wReserved3: u16,
+    pub Anonymous: VARIANT_0_0_0,
+}
+
+#[repr(C)]
+pub union VARIANT_0_0_0 {
+    pub llVal: i64,
+    pub lVal: i32,
+    pub bVal: u8,
+    pub iVal: i16,
+    pub fltVal: f32,
+    pub dblVal: f64,
+    pub boolVal: VARIANT_BOOL,
+    pub scode: i32,
+    pub cyVal: CY,
+    pub date: f64,
+    pub bstrVal: ManuallyDrop<BSTR>,
+    pub punkVal: ManuallyDrop<Option<IUnknown>>,
+    pub pdispVal: ManuallyDrop<Option<IDispatch>>,
+    pub parray: *mut SAFEARRAY,
+    pub pbVal: *mut u8,
+    pub piVal: *mut i16,
+    pub plVal: *mut i32,
+    pub pllVal: *mut i64,
+    pub pfltVal: *mut f32,
+    pub pdblVal: *mut f64,
+    pub pboolVal: *mut VARIANT_BOOL,
+    pub pscode: *mut i32,
+    pub pcyVal: *mut CY,
+    pub pdate: *mut f64,
+    pub pbstrVal: *mut BSTR,
+    pub ppunkVal: *mut Option<IUnknown>,
+    pub ppdispVal: *mut Option<IDispatch>,
+    pub pparray: *mut *mut SAFEARRAY,
+    pub pvarVal: *mut VARIANT,
+    pub byref: *mut c_void,
+    pub cVal: u8,
+    pub uiVal: u16,
+    pub ulVal: u32,
+    pub ullVal: u64,
+    pub intVal: i32,
+    pub uintVal: u32,
+    pub pdecVal: *mut DECIMAL,
+    pub pcVal: PSTR,
+    pub puiVal: *mut u16,
+    pub pulVal: *mut u32,
+    pub pullVal: *mut u64,
+    pub pintVal: *mut i32,
+    pub puintVal: *mut u32,
+    pub Anonymous: ManuallyDrop<VARIANT_0_0_0_0>,
+}
+
+#[repr(C)]
+pub struct VARIANT_0_0_0_0 {
+    pub pvRecord: *mut c_void,
+    pub pRecInfo: ManuallyDrop<Option<IRecordInfo>>,
+}
+
+plain!(
+    CY,
+    CY_0,
+    DECIMAL,
+    DECIMAL_0,
+    DECIMAL_0_0,
+    DECIMAL_1,
+    DECIMAL_1_0,
+    VARIANT,
+    VARIANT_0,
+    VARIANT_0_0,
+    VARIANT_0_0_0,
+    VARIANT_0_0_0_0
+);
+
+/// Frees the value held by a variant and resets it to `VT_EMPTY`, like `VariantClear`.
+/// By-reference values are left untouched.
+///
+/// # Safety
+/// `pvarg` must be null or point to a valid [`VARIANT`] whose strings and arrays were allocated
+/// by this module.
+pub unsafe fn VariantClear(pvarg: *mut VARIANT) -> HRESULT {
+    let Some(var) = pvarg.as_mut() else {
+        return E_INVALIDARG;
+    };
+    let val = &mut *var.Anonymous.Anonymous;
+    if val.vt.0 & VT_BYREF.0 == 0 {
+        if val.vt.0 & VT_ARRAY.0 != 0 {
+            let hr = SafeArrayDestroy(val.Anonymous.parray);
+            if hr.is_err() {
+                return hr;
+            }
+        } else {
+            match val.vt {
+                VT_BSTR => ManuallyDrop::drop(&mut val.Anonymous.bstrVal),
+                VT_UNKNOWN => ManuallyDrop::drop(&mut val.Anonymous.punkVal),
+                VT_DISPATCH => ManuallyDrop::drop(&mut val.Anonymous.pdispVal),
+                VT_RECORD => {
+                    let rec = &mut *val.Anonymous.Anonymous;
+                    if let Some(info) = rec.pRecInfo.as_ref() {
+                        // through the vtable, as the wrapper method needs oleaut32 to report errors
+                        let _ = (info.vtable().RecordDestroy)(info.as_raw(), rec.pvRecord);
+                    }
+                    ManuallyDrop::drop(&mut rec.pRecInfo);
+                }
+                _ => {}
+            }
+        }
+    }
+    *var = VARIANT::default();
+    S_OK
+}
+
+/// Size and features of the elements of an array
+fn element_info(vt: VARENUM) -> Option<(usize, ADVANCED_FEATURE_FLAGS)> {
+    let none = ADVANCED_FEATURE_FLAGS(0);
+    Some(match vt {
+        VT_I1 | VT_UI1 => (1, none),
+        VT_I2 | VT_UI2 | VT_BOOL => (2, none),
+        VT_I4 | VT_UI4 | VT_INT | VT_UINT | VT_R4 | VT_ERROR => (4, none),
+        VT_I8 | VT_UI8 | VT_R8 | VT_CY | VT_DATE => (8, none),
+        VT_DECIMAL => (size_of::<DECIMAL>(), none),
+        VT_BSTR => (size_of::<BSTR>(), FADF_BSTR),
+        VT_UNKNOWN => (size_of::<usize>(), FADF_UNKNOWN),
+        VT_DISPATCH => (size_of::<usize>(), FADF_DISPATCH),
+        VT_VARIANT => (size_of::<VARIANT>(), FADF_VARIANT),
+        _ => return None,
+    })
+}
+
+/// Header and bounds of an array of at least one dimension
+fn header_layout(dims: usize) -> Layout {
+    Layout::from_size_align(
+        size_of::<SAFEARRAY>() + (dims - 1) * size_of::<SAFEARRAYBOUND>(),
+        align_of::<SAFEARRAY>(),
+    )
+    .unwrap()
+}
+
+fn data_layout(size: usize, count: usize) -> Option<Layout> {
+    Layout::from_size_align(size.checked_mul(count)?, align_of::<VARIANT>()).ok()
+}
+
+/// Allocates an array of zeroed elements, like `SafeArrayCreate`. Returns null if the element
+/// type isn't supported or the array is too large.
+///
+/// # Safety
+/// `rgsabound` must point to `cdims` bounds.
+pub unsafe fn SafeArrayCreate(
+    vt: VARENUM,
+    cdims: u32,
+    rgsabound: *const SAFEARRAYBOUND,
+) -> *mut SAFEARRAY {
+    let Some((size, features)) = element_info(vt) else {
+        return null_mut();
+    };
+    let Ok(dims) = u16::try_from(cdims) else {
+        return null_mut();
+    };
+    if dims == 0 || rgsabound.is_null() {
+        return null_mut();
+    }
+    let bounds = std::slice::from_raw_parts(rgsabound, dims as usize);
+    let Some(layout) = bounds
+        .iter()
+        .try_fold(1usize, |n, b| n.checked_mul(b.cElements as usize))
+        .and_then(|count| data_layout(size, count))
+    else {
+        return null_mut();
+    };
+
+    let header = header_layout(dims as usize);
+    let psa = alloc_zeroed(header) as *mut SAFEARRAY;
+    if psa.is_null() {
+        handle_alloc_error(header);
+    }
+    let data = if layout.size() == 0 {
+        null_mut()
+    } else {
+        let data = alloc_zeroed(layout);
+        if data.is_null() {
+            handle_alloc_error(layout);
+        }
+        data
+    };
+    let arr = &mut *psa;
+    arr.cDims = dims;
+    arr.fFeatures = features;
+    arr.cbElements = size as u32;
+    arr.pvData = data as *mut c_void;
+    // bounds are stored in reverse order
+    let dst = std::slice::from_raw_parts_mut(arr.rgsabound.as_mut_ptr(), dims as usize);
+    for (dst, src) in dst.iter_mut().zip(bounds.iter().rev()) {
+        *dst = *src;
+    }
+    psa
+}
+
+/// Frees an array created by [`SafeArrayCreate`] and the strings, interfaces and variants it
+/// holds, like `SafeArrayDestroy`.
+///
+/// # Safety
+/// `psa` must be null or have been returned by [`SafeArrayCreate`].
+pub unsafe fn SafeArrayDestroy(psa: *const SAFEARRAY) -> HRESULT {
+    let Some(arr) = psa.as_ref() else {
+        return S_OK;
+    };
+    if arr.cLocks != 0 {
+        return DISP_E_ARRAYISLOCKED;
+    }
+    // arrays without dimensions can't be created by this module
+    if arr.cDims == 0 {
+        return E_INVALIDARG;
+    }
+    let size = arr.cbElements as usize;
+    let count = std::slice::from_raw_parts(arr.rgsabound.as_ptr(), arr.cDims as usize)
+        .iter()
+        .map(|b| b.cElements as usize)
+        .product();
+    let data = arr.pvData as *mut u8;
+    if !data.is_null() {
+        for i in 0..count {
+            let ptr = data.add(i * size);
+            if arr.fFeatures.0 & FADF_BSTR.0 != 0 {
+                std::ptr::drop_in_place(ptr as *mut BSTR);
+            } else if arr.fFeatures.0 & (FADF_UNKNOWN.0 | FADF_DISPATCH.0) != 0 {
+                std::ptr::drop_in_place(ptr as *mut Option<IUnknown>);
+            } else if arr.fFeatures.0 & FADF_VARIANT.0 != 0 {
+                let _ = VariantClear(ptr as *mut VARIANT);
+            }
+        }
+        dealloc(data, data_layout(size, count).unwrap());
+    }
+    dealloc(psa as *mut u8, header_layout(arr.cDims as usize));
+    S_OK
+}
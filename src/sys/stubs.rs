@@ -0,0 +1,151 @@
+//! Fallbacks for the Win32 functions that the `windows` crate expects the platform to provide,
+//! for the `win32-stubs` feature.
+//!
+//! The `windows` crate refers to these functions from code this crate doesn't call, such as the
+//! creation of `windows::core::Error` values, so a binary using the `portable` backend doesn't
+//! link unless something defines them. The fallbacks are hidden weak symbols: they are not
+//! exported, and only a definition linked statically into the same binary replaces them. In
+//! particular, they are used instead of the functions of shared libraries such as Wine's, for the
+//! whole binary. They behave as if no error information or library were available.
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+compile_error!("the `win32-stubs` feature is only supported on x86-64 and AArch64");
+
+use super::BSTR;
+use std::alloc::{alloc_zeroed, dealloc, Layout};
+use std::ffi::c_void;
+use std::mem::ManuallyDrop;
+use std::ptr::null_mut;
+use windows::core::{HRESULT, PSTR};
+use windows::Win32::Foundation::S_FALSE;
+
+/// Defines hidden weak symbols jumping to the given functions
+macro_rules! fallback {
+    ($($name:literal => $target:ident),* $(,)?) => {
+        $(
+            #[cfg(not(target_vendor = "apple"))]
+            #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+            std::arch::global_asm!(
+                concat!(".pushsection .text.", $name, ",\"ax\",%progbits"),
+                concat!(".weak ", $name),
+                concat!(".hidden ", $name),
+                concat!(".type ", $name, ",%function"),
+                concat!($name, ":"),
+                jump!(),
+                concat!(".size ", $name, ",.-", $name),
+                ".popsection",
+                target = sym $target,
+            );
+
+            #[cfg(target_vendor = "apple")]
+            #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+            std::arch::global_asm!(
+                ".text",
+                concat!(".globl _", $name),
+                concat!(".weak_definition _", $name),
+                concat!(".private_extern _", $name),
+                ".p2align 2",
+                concat!("_", $name, ":"),
+                jump!(),
+                target = sym $target,
+            );
+        )*
+    };
+}
+
+#[cfg(target_arch = "x86_64")]
+macro_rules! jump {
+    () => {
+        "jmp {target}"
+    };
+}
+
+#[cfg(target_arch = "aarch64")]
+macro_rules! jump {
+    () => {
+        "b {target}"
+    };
+}
+
+fallback!(
+    "SysStringLen" => sys_string_len,
+    "SysFreeString" => sys_free_string,
+    "GetErrorInfo" => get_error_info,
+    "FormatMessageW" => format_message,
+    "LoadLibraryExA" => load_library,
+    "GetProcAddress" => get_proc_address,
+    "FreeLibrary" => free_library,
+    "GetProcessHeap" => get_process_heap,
+    "HeapAlloc" => heap_alloc,
+    "HeapFree" => heap_free,
+);
+
+unsafe extern "system" fn sys_string_len(bstr: *const u16) -> u32 {
+    ManuallyDrop::new(BSTR::from_raw(bstr)).len() as u32
+}
+
+unsafe extern "system" fn sys_free_string(bstr: *const u16) {
+    drop(BSTR::from_raw(bstr));
+}
+
+unsafe extern "system" fn get_error_info(_reserved: u32, info: *mut *mut c_void) -> HRESULT {
+    *info = null_mut();
+    S_FALSE
+}
+
+unsafe extern "system" fn format_message(
+    _flags: u32,
+    _source: *const c_void,
+    _id: u32,
+    _language: u32,
+    _buffer: *mut u16,
+    _size: u32,
+    _arguments: *const *const i8,
+) -> u32 {
+    0
+}
+
+/// Fails, so that the `windows` crate doesn't look up functions in the library
+unsafe extern "system" fn load_library(_name: PSTR, _file: isize, _flags: u32) -> isize {
+    0
+}
+
+unsafe extern "system" fn get_proc_address(_library: isize, _name: PSTR) -> *const c_void {
+    std::ptr::null()
+}
+
+unsafe extern "system" fn free_library(_library: isize) -> i32 {
+    1
+}
+
+unsafe extern "system" fn get_process_heap() -> isize {
+    1
+}
+
+const HEAP_HEADER: usize = 16;
+
+/// Size and alignment of a heap block, whose size is stored in its first bytes
+fn heap_layout(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size.checked_add(HEAP_HEADER)?, HEAP_HEADER).ok()
+}
+
+unsafe extern "system" fn heap_alloc(_heap: isize, _flags: u32, size: usize) -> *mut c_void {
+    let Some(layout) = heap_layout(size) else {
+        return null_mut();
+    };
+    let ptr = alloc_zeroed(layout);
+    if ptr.is_null() {
+        return null_mut();
+    }
+    (ptr as *mut usize).write(size);
+    ptr.add(HEAP_HEADER) as *mut c_void
+}
+
+unsafe extern "system" fn heap_free(_heap: isize, _flags: u32, ptr: *const c_void) -> i32 {
+    if !ptr.is_null() {
+        let ptr = (ptr as *mut u8).sub(HEAP_HEADER);
+        let size = (ptr as *const usize).read();
+        dealloc(ptr, heap_layout(size).unwrap());
+    }
+    1
+}
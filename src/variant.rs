@@ -1,31 +1,37 @@
 //! Rust wrapper for the [`VARIANT`] type
 
+use crate::coerce::{copy_value, dereference, VAR_CHANGE_FLAGS};
 use crate::com_types::currency::{ComCurrency, Currency};
 use crate::com_types::date::ComDate;
 use crate::com_types::decimal::ComDecimal;
+use crate::com_types::record::Record;
+use crate::com_types::safearray::{SafeArray, SafeArrayPtr};
+use crate::convert::VariantConversionError;
+use crate::sys::{BSTR, VARIANT};
 //use crate::com_types::string::ComString;
-use crate::{ComBool, PtrWrapper};
+use crate::{ComBool, OwnedVariant, PtrWrapper};
 use chrono::NaiveDateTime;
 use enumn::N;
 use rust_decimal::Decimal;
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
 
 use paste::paste;
 use windows::core::IUnknown;
 use windows::core::HRESULT;
-use windows::core::{BSTR, PSTR};
 use windows::Win32::System::Com::IDispatch;
-use windows::Win32::System::Variant::VARIANT;
+use windows::Win32::System::Variant::VARENUM;
 
 macro_rules! variant_enum {
     (@impl $name:ident) => {};
 
-    (@impl $name:ident (&'static $($type:tt)+)) => {};
+    (@impl $name:ident (&'a $($type:tt)+)) => {};
 
     (@impl $name:ident (@@ $type:ty)) => {};
 
     (@impl $name:ident (Option<$type:ty>)) => {
         impl ToVariant for $type {
-            fn to_variant(self) -> Variant {
+            fn to_variant(self) -> Variant<'static> {
                 Variant::$name(Some(self))
             }
         }
@@ -35,7 +41,7 @@ macro_rules! variant_enum {
 
     (@impl $(@opt)? $name:ident ($type:ty)) => {
         impl ToVariant for $type {
-            fn to_variant(self) -> Variant {
+            fn to_variant(self) -> Variant<'static> {
                 Variant::$name(self)
             }
         }
@@ -58,15 +64,15 @@ macro_rules! variant_enum {
     (@enum $($name:ident $(( $(@@)? $( $type:ty )+ ) )?),* $(,)?) => {
         #[derive(Debug, PartialEq)]
         #[allow(clippy::enum_variant_names)]
-        pub enum Variant {
+        pub enum Variant<'a> {
             $($name $(($($type)+))?),*
         }
 
         paste! {
             #[allow(unused_parens)]
-            impl Variant {
+            impl<'a> Variant<'a> {
                 $(
-                    pub fn [< try_ $name:lower >](self) -> Result<( $($( $type )+)? ), Variant> {
+                    pub fn [< try_ $name:lower >](self) -> Result<( $($( $type )+)? ), Variant<'a>> {
                         variant_enum!(@match self, $name, $($( $type )+)?)
                     }
 
@@ -93,64 +99,74 @@ variant_enum! {
     Null,
 
     Bool(bool),
-    BoolRef(&'static mut ComBool),
+    BoolRef(&'a mut ComBool),
 
     I8(i8),
-    I8Ref(PSTR),
+    I8Ref(&'a mut i8),
     I16(i16),
-    I16Ref(&'static mut i16),
+    I16Ref(&'a mut i16),
     I32(i32),
-    I32Ref(&'static mut i32),
+    I32Ref(&'a mut i32),
     I64(i64),
-    I64Ref(&'static mut i64),
+    I64Ref(&'a mut i64),
 
     U8(u8),
-    U8Ref(&'static mut u8),
+    U8Ref(&'a mut u8),
     U16(u16),
-    U16Ref(&'static mut u16),
+    U16Ref(&'a mut u16),
     U32(u32),
-    U32Ref(&'static mut u32),
+    U32Ref(&'a mut u32),
     U64(u64),
-    U64Ref(&'static mut u64),
+    U64Ref(&'a mut u64),
+
+    Int(@@ i32),
+    IntRef(&'a mut i32),
+    UInt(@@ u32),
+    UIntRef(&'a mut u32),
+    IntPtr(isize),
+    UIntPtr(usize),
 
     F32(f32),
-    F32Ref(&'static mut f32),
+    F32Ref(&'a mut f32),
     F64(f64),
-    F64Ref(&'static mut f64),
+    F64Ref(&'a mut f64),
 
     Currency(Currency),
-    CurrencyRef(&'static mut ComCurrency),
+    CurrencyRef(&'a mut ComCurrency),
 
     Decimal(Decimal),
-    DecimalRef(&'static mut ComDecimal),
+    DecimalRef(&'a mut ComDecimal),
 
     Date(NaiveDateTime),
-    DateRef(&'static mut ComDate),
+    DateRef(&'a mut ComDate),
 
     String(BSTR),
-    StringRef(&'static mut BSTR),
+    StringRef(&'a mut BSTR),
 
     Dispatch(Option<IDispatch>),
+    DispatchRef(&'a mut Option<IDispatch>),
     Unknown(Option<IUnknown>),
+    UnknownRef(&'a mut Option<IUnknown>),
 
     Error(@@ HRESULT),
-    ErrorRef(&'static mut HRESULT),
+    ErrorRef(&'a mut HRESULT),
+
+    VariantRef(@@ PtrWrapper<'a, VARIANT>),
 
-    VariantRef(PtrWrapper<VARIANT>),
+    Array(SafeArray),
+    ArrayRef(@@ SafeArrayPtr<'a>),
 
-    //Record(),
+    Record(Record),
+
+    Raw(@@ OwnedVariant),
 }
 
-impl Clone for Variant {
-    fn clone(&self) -> Self {
+impl<'a> Variant<'a> {
+    /// Clones a by-value variant. By-reference values borrow their target mutably and can't be
+    /// cloned, see [`Variant::deref_clone`], and neither can raw values.
+    pub fn try_clone(&self) -> Result<Variant<'static>, VariantConversionError> {
         use Variant::*;
-        match self {
-            BoolRef(_) | I8Ref(_) | I16Ref(_) | I32Ref(_) | I64Ref(_) | U8Ref(_) | U16Ref(_)
-            | U32Ref(_) | U64Ref(_) | F32Ref(_) | F64Ref(_) | CurrencyRef(_) | DecimalRef(_)
-            | DateRef(_) | StringRef(_) | ErrorRef(_) | VariantRef(_) => {
-                panic!("Cannot clone a reference variant")
-            }
-
+        Ok(match self {
             Empty => Empty,
             Null => Null,
             Bool(x) => Bool(*x),
@@ -162,6 +178,10 @@ impl Clone for Variant {
             U16(x) => U16(*x),
             U32(x) => U32(*x),
             U64(x) => U64(*x),
+            Int(x) => Int(*x),
+            UInt(x) => UInt(*x),
+            IntPtr(x) => IntPtr(*x),
+            UIntPtr(x) => UIntPtr(*x),
             F32(x) => F32(*x),
             F64(x) => F64(*x),
             Currency(x) => Currency(*x),
@@ -171,45 +191,215 @@ impl Clone for Variant {
             Dispatch(x) => Dispatch(x.clone()),
             Unknown(x) => Unknown(x.clone()),
             Error(x) => Error(*x),
+            Array(x) => Array(x.try_clone()?),
+            Record(x) => Record(x.try_clone()?),
+
+            Raw(x) => return Err(VariantConversionError::RawValue(x.vt())),
+            BoolRef(_) | I8Ref(_) | I16Ref(_) | I32Ref(_) | I64Ref(_) | U8Ref(_) | U16Ref(_)
+            | U32Ref(_) | U64Ref(_) | IntRef(_) | UIntRef(_) | F32Ref(_) | F64Ref(_)
+            | CurrencyRef(_) | DecimalRef(_) | DateRef(_) | StringRef(_) | DispatchRef(_)
+            | UnknownRef(_) | ErrorRef(_) | VariantRef(_) | ArrayRef(_) => {
+                return Err(VariantConversionError::ReferenceClone(self.var_type()?))
+            }
+        })
+    }
+
+    /// Copies the value, reading through references like `VariantCopyInd`: `I32Ref` gives `I32`,
+    /// `ArrayRef` a copy of the array, and `VariantRef` the value of the referenced variant,
+    /// itself dereferenced.
+    pub fn deref_clone(&self) -> Result<Variant<'static>, VariantConversionError> {
+        copy_value(self)
+    }
+}
+
+/// Panics for by-reference and raw values, see [`Variant::try_clone`]
+impl<'a> Clone for Variant<'a> {
+    fn clone(&self) -> Self {
+        match self.try_clone() {
+            Ok(value) => value,
+            Err(e) => panic!("Cannot clone the variant: {}", e),
         }
     }
 }
 
 pub trait ToVariant {
-    fn to_variant(self) -> Variant;
+    fn to_variant(self) -> Variant<'static>;
 }
 
 impl ToVariant for () {
-    fn to_variant(self) -> Variant {
+    fn to_variant(self) -> Variant<'static> {
         Variant::Null
     }
 }
 
 impl ToVariant for &str {
-    fn to_variant(self) -> Variant {
+    fn to_variant(self) -> Variant<'static> {
         Variant::String(BSTR::from(self))
     }
 }
 
 impl ToVariant for String {
-    fn to_variant(self) -> Variant {
+    fn to_variant(self) -> Variant<'static> {
         Variant::String(BSTR::from(self))
     }
 }
 
+impl<T: ToVariant> ToVariant for Vec<T> {
+    fn to_variant(self) -> Variant<'static> {
+        Variant::Array(SafeArray::from_vec(
+            self.into_iter().map(ToVariant::to_variant).collect(),
+        ))
+    }
+}
+
 impl<T: Clone + ToVariant> ToVariant for &T {
-    fn to_variant(self) -> Variant {
+    fn to_variant(self) -> Variant<'static> {
         self.clone().to_variant()
     }
 }
 
-impl<T: ToVariant> From<T> for Variant {
+impl<'a, T: ToVariant> From<T> for Variant<'a> {
     fn from(t: T) -> Self {
         t.to_variant()
     }
 }
 
-#[derive(N, Debug, PartialEq, Eq, Copy, Clone)]
+/// Conversion from a [`Variant`], the counterpart of [`ToVariant`].
+///
+/// Scalar types are converted with [`Variant::change_type`], so `"12"` can be read as an
+/// [`i32`] and `I16(5)` as an [`f64`]. `Null` and `Empty` give `None` when reading an
+/// [`Option`]. Arrays of any dimension are read as a [`Vec`] in memory order, and as tuples when
+/// the number of elements matches.
+pub trait FromVariant: Sized {
+    fn from_variant(variant: Variant<'_>) -> Result<Self, VariantConversionError>;
+}
+
+macro_rules! from_variant {
+    ( $( $type:ty => $vt:ident $name:ident ),* ) => {
+        $(
+            impl FromVariant for $type {
+                fn from_variant(variant: Variant<'_>) -> Result<Self, VariantConversionError> {
+                    match variant.change_type(VariantType::$vt, VAR_CHANGE_FLAGS(0))? {
+                        Variant::$name(v) => Ok(v),
+                        _ => Err(VariantConversionError::TypeMismatch(VariantType::$vt)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+from_variant! {
+    bool => VT_BOOL Bool,
+    i8 => VT_I1 I8,
+    i16 => VT_I2 I16,
+    i32 => VT_I4 I32,
+    i64 => VT_I8 I64,
+    u8 => VT_UI1 U8,
+    u16 => VT_UI2 U16,
+    u32 => VT_UI4 U32,
+    u64 => VT_UI8 U64,
+    isize => VT_INT_PTR IntPtr,
+    usize => VT_UINT_PTR UIntPtr,
+    f32 => VT_R4 F32,
+    f64 => VT_R8 F64,
+    Currency => VT_CY Currency,
+    Decimal => VT_DECIMAL Decimal,
+    NaiveDateTime => VT_DATE Date,
+    BSTR => VT_BSTR String
+}
+
+impl FromVariant for String {
+    fn from_variant(variant: Variant<'_>) -> Result<Self, VariantConversionError> {
+        Ok(String::from_utf16(BSTR::from_variant(variant)?.as_wide())?)
+    }
+}
+
+impl FromVariant for Variant<'static> {
+    fn from_variant(variant: Variant<'_>) -> Result<Self, VariantConversionError> {
+        dereference(variant)
+    }
+}
+
+impl<T: FromVariant> FromVariant for Option<T> {
+    fn from_variant(variant: Variant<'_>) -> Result<Self, VariantConversionError> {
+        match dereference(variant)? {
+            Variant::Null | Variant::Empty => Ok(None),
+            variant => T::from_variant(variant).map(Some),
+        }
+    }
+}
+
+impl<T: FromVariant> FromVariant for Vec<T> {
+    fn from_variant(variant: Variant<'_>) -> Result<Self, VariantConversionError> {
+        match dereference(variant)? {
+            Variant::Array(arr) => arr
+                .into_elements()
+                .into_iter()
+                .map(T::from_variant)
+                .collect(),
+            _ => Err(VariantConversionError::TypeMismatch(
+                VariantType::VT_SAFEARRAY,
+            )),
+        }
+    }
+}
+
+impl FromVariant for SafeArray {
+    fn from_variant(variant: Variant<'_>) -> Result<Self, VariantConversionError> {
+        match dereference(variant)? {
+            Variant::Array(arr) => Ok(arr),
+            _ => Err(VariantConversionError::TypeMismatch(
+                VariantType::VT_SAFEARRAY,
+            )),
+        }
+    }
+}
+
+impl FromVariant for Record {
+    fn from_variant(variant: Variant<'_>) -> Result<Self, VariantConversionError> {
+        match dereference(variant)? {
+            Variant::Record(rec) => Ok(rec),
+            _ => Err(VariantConversionError::TypeMismatch(VariantType::VT_RECORD)),
+        }
+    }
+}
+
+macro_rules! from_variant_tuple {
+    ( $( ($($t:ident),+) ),* ) => {
+        $(
+            impl<$($t: FromVariant),+> FromVariant for ($($t,)+) {
+                fn from_variant(variant: Variant<'_>) -> Result<Self, VariantConversionError> {
+                    let elements = Vec::<Variant<'static>>::from_variant(variant)?;
+                    if elements.len() != [$(stringify!($t)),+].len() {
+                        return Err(VariantConversionError::ArrayBoundsMismatch);
+                    }
+                    let mut elements = elements.into_iter();
+                    Ok(($($t::from_variant(elements.next().unwrap())?,)+))
+                }
+            }
+        )*
+    };
+}
+
+from_variant_tuple! {
+    (A),
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F)
+}
+
+impl<'a> Variant<'a> {
+    /// Converts the value to a Rust type, see [`FromVariant`]
+    pub fn get<T: FromVariant>(self) -> Result<T, VariantConversionError> {
+        T::from_variant(self)
+    }
+}
+
+#[derive(N, Debug, PartialEq, Eq, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 pub enum VariantType {
     VT_EMPTY = 0,
@@ -246,12 +436,225 @@ pub enum VariantType {
     VT_RECORD = 36,
     VT_INT_PTR = 37,
     VT_UINT_PTR = 38,
+    VT_FILETIME = 64,
+    VT_BLOB = 65,
+    VT_STREAM = 66,
+    VT_STORAGE = 67,
+    VT_STREAMED_OBJECT = 68,
+    VT_STORED_OBJECT = 69,
+    VT_BLOB_OBJECT = 70,
+    VT_CF = 71,
+    VT_CLSID = 72,
+    VT_VERSIONED_STREAM = 73,
 }
 
+pub const VT_VECTOR: u16 = 4096;
+pub const VT_ARRAY: u16 = 8192;
 pub const VT_BYREF: u16 = 16384;
 
 impl VariantType {
     pub fn byref(self) -> u16 {
         self as u16 | VT_BYREF
     }
+
+    pub fn array(self) -> u16 {
+        self as u16 | VT_ARRAY
+    }
+
+    pub fn vector(self) -> u16 {
+        self as u16 | VT_VECTOR
+    }
+}
+
+impl Display for VariantType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+/// Parses a type name such as `VT_I4`
+impl FromStr for VariantType {
+    type Err = VariantConversionError;
+
+    fn from_str(s: &str) -> Result<Self, VariantConversionError> {
+        (0..=VariantType::VT_VERSIONED_STREAM as u16)
+            .filter_map(VariantType::n)
+            .find(|vt| vt.to_string() == s)
+            .ok_or_else(|| VariantConversionError::UnknownTypeName(s.to_string()))
+    }
+}
+
+pub const VT_RESERVED: u16 = 32768;
+
+const FLAGS: [(u16, &str); 4] = [
+    (VT_VECTOR, "VT_VECTOR"),
+    (VT_ARRAY, "VT_ARRAY"),
+    (VT_BYREF, "VT_BYREF"),
+    (VT_RESERVED, "VT_RESERVED"),
+];
+
+/// Full type of a [`VARIANT`] or `PROPVARIANT`: a base type and the flags combined with it
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+pub struct VarType {
+    pub base: VariantType,
+    /// `VT_VECTOR`, `VT_ARRAY`, `VT_BYREF` and `VT_RESERVED` bits
+    pub flags: u16,
+}
+
+impl VarType {
+    pub fn new(base: VariantType) -> Self {
+        VarType { base, flags: 0 }
+    }
+
+    pub fn byref(self) -> Self {
+        self.with(VT_BYREF)
+    }
+
+    pub fn array(self) -> Self {
+        self.with(VT_ARRAY)
+    }
+
+    pub fn vector(self) -> Self {
+        self.with(VT_VECTOR)
+    }
+
+    fn with(self, flag: u16) -> Self {
+        VarType {
+            flags: self.flags | flag,
+            ..self
+        }
+    }
+
+    pub fn is_byref(self) -> bool {
+        self.flags & VT_BYREF != 0
+    }
+
+    pub fn is_array(self) -> bool {
+        self.flags & VT_ARRAY != 0
+    }
+
+    pub fn is_vector(self) -> bool {
+        self.flags & VT_VECTOR != 0
+    }
+
+    pub fn is_reserved(self) -> bool {
+        self.flags & VT_RESERVED != 0
+    }
+}
+
+impl From<VariantType> for VarType {
+    fn from(base: VariantType) -> Self {
+        VarType::new(base)
+    }
+}
+
+impl From<VarType> for VARENUM {
+    fn from(vt: VarType) -> Self {
+        VARENUM(vt.base as u16 | vt.flags)
+    }
+}
+
+/// Fails with [`VariantConversionError::UnknownType`] if the base type is unknown
+impl TryFrom<VARENUM> for VarType {
+    type Error = VariantConversionError;
+
+    fn try_from(vt: VARENUM) -> Result<Self, VariantConversionError> {
+        let flags = FLAGS.iter().fold(0, |acc, (flag, _)| acc | flag);
+        Ok(VarType {
+            base: VariantType::n(vt.0 & !flags).ok_or(VariantConversionError::UnknownType(vt))?,
+            flags: vt.0 & flags,
+        })
+    }
+}
+
+/// Writes the flags followed by the base type, e.g. `VT_ARRAY|VT_I4`
+impl Display for VarType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (flag, name) in FLAGS {
+            if self.flags & flag != 0 {
+                write!(f, "{}|", name)?;
+            }
+        }
+        write!(f, "{}", self.base)
+    }
+}
+
+/// Parses names separated by `|`, in any order, e.g. `VT_ARRAY|VT_I4`. There must be exactly one
+/// base type.
+impl FromStr for VarType {
+    type Err = VariantConversionError;
+
+    fn from_str(s: &str) -> Result<Self, VariantConversionError> {
+        let mut base = None;
+        let mut flags = 0;
+        for name in s.split('|').map(str::trim) {
+            match FLAGS.iter().find(|(_, n)| *n == name) {
+                Some((flag, _)) => flags |= flag,
+                None if base.is_none() => base = Some(name.parse()?),
+                None => return Err(VariantConversionError::UnknownTypeName(s.to_string())),
+            }
+        }
+        let base = base.ok_or_else(|| VariantConversionError::UnknownTypeName(s.to_string()))?;
+        Ok(VarType { base, flags })
+    }
+}
+
+impl<'a> Variant<'a> {
+    /// Type of the `VARIANT` the value is converted to. Fails with
+    /// [`VariantConversionError::UnknownType`] for a [`Variant::Raw`] of an unknown type.
+    pub fn var_type(&self) -> Result<VarType, VariantConversionError> {
+        use Variant::*;
+        use VariantType::*;
+        Ok(match self {
+            Empty => VT_EMPTY.into(),
+            Null => VT_NULL.into(),
+            Bool(_) => VT_BOOL.into(),
+            BoolRef(_) => VarType::new(VT_BOOL).byref(),
+            I8(_) => VT_I1.into(),
+            I8Ref(_) => VarType::new(VT_I1).byref(),
+            I16(_) => VT_I2.into(),
+            I16Ref(_) => VarType::new(VT_I2).byref(),
+            I32(_) => VT_I4.into(),
+            I32Ref(_) => VarType::new(VT_I4).byref(),
+            I64(_) => VT_I8.into(),
+            I64Ref(_) => VarType::new(VT_I8).byref(),
+            U8(_) => VT_UI1.into(),
+            U8Ref(_) => VarType::new(VT_UI1).byref(),
+            U16(_) => VT_UI2.into(),
+            U16Ref(_) => VarType::new(VT_UI2).byref(),
+            U32(_) => VT_UI4.into(),
+            U32Ref(_) => VarType::new(VT_UI4).byref(),
+            U64(_) => VT_UI8.into(),
+            U64Ref(_) => VarType::new(VT_UI8).byref(),
+            Int(_) => VT_INT.into(),
+            IntRef(_) => VarType::new(VT_INT).byref(),
+            UInt(_) => VT_UINT.into(),
+            UIntRef(_) => VarType::new(VT_UINT).byref(),
+            IntPtr(_) => VT_INT_PTR.into(),
+            UIntPtr(_) => VT_UINT_PTR.into(),
+            F32(_) => VT_R4.into(),
+            F32Ref(_) => VarType::new(VT_R4).byref(),
+            F64(_) => VT_R8.into(),
+            F64Ref(_) => VarType::new(VT_R8).byref(),
+            Currency(_) => VT_CY.into(),
+            CurrencyRef(_) => VarType::new(VT_CY).byref(),
+            Decimal(_) => VT_DECIMAL.into(),
+            DecimalRef(_) => VarType::new(VT_DECIMAL).byref(),
+            Date(_) => VT_DATE.into(),
+            DateRef(_) => VarType::new(VT_DATE).byref(),
+            String(_) => VT_BSTR.into(),
+            StringRef(_) => VarType::new(VT_BSTR).byref(),
+            Dispatch(_) => VT_DISPATCH.into(),
+            DispatchRef(_) => VarType::new(VT_DISPATCH).byref(),
+            Unknown(_) => VT_UNKNOWN.into(),
+            UnknownRef(_) => VarType::new(VT_UNKNOWN).byref(),
+            Error(_) => VT_ERROR.into(),
+            ErrorRef(_) => VarType::new(VT_ERROR).byref(),
+            VariantRef(_) => VarType::new(VT_VARIANT).byref(),
+            Array(a) => VarType::new(a.element_type()).array(),
+            ArrayRef(a) => VarType::new(a.element_type).array().byref(),
+            Record(_) => VT_RECORD.into(),
+            Raw(var) => return var.vt().try_into(),
+        })
+    }
 }
@@ -0,0 +1,308 @@
+//! Derive macros for the `ToVariant` and `FromVariant` traits of
+//! [variant-rs](https://docs.rs/variant-rs). Use them through the `derive` feature of variant-rs
+//! rather than depending on this crate directly.
+//!
+//! - Structs with named fields are converted to records (`VT_RECORD`) whose type name is the
+//!   name of the struct, or to arrays of their fields with `#[variant(array)]`
+//! - Tuple structs with one field (newtypes) are converted like their field
+//! - Other tuple structs are converted to arrays of their fields
+//! - Unit structs are converted to `Empty`
+//! - C-like enums are converted to `I32` constants
+//!
+//! The following attributes are supported:
+//! - `#[variant(array)]` on a struct converts it to an array of its fields in declaration order
+//! - `#[variant(name = "...")]` on a struct sets the type name of the record
+//! - `#[variant(record_info = path)]` on a struct sets the `IRecordInfo` of the record to the
+//!   result of calling the function `path`, of type `fn() -> Option<IRecordInfo>`. Records need it
+//!   to be converted to a `VARIANT`, and fail with `MissingRecordInfo` otherwise
+//! - `#[variant(rename = "...")]` on a field sets its name in the record, and is rejected on fields
+//!   of other structs
+//!
+//! Type parameters must implement the derived trait.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Error, Field, Fields, Generics,
+    Ident, LitStr, Member, Path, Result,
+};
+
+/// How a type is converted
+enum Shape {
+    /// Record with the given type name, function returning its record info, and the field names
+    /// of each member
+    Record(String, Option<Path>, Vec<(Member, String)>),
+    /// Array of the given members
+    Array(Vec<Member>),
+    Newtype,
+    Unit,
+    /// C-like enum with the given variants
+    Enum(Vec<Ident>),
+}
+
+#[derive(Default)]
+struct ContainerOptions {
+    array: bool,
+    name: Option<String>,
+    record_info: Option<Path>,
+}
+
+fn container_options(attrs: &[Attribute]) -> Result<ContainerOptions> {
+    let mut options = ContainerOptions::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("variant")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("array") {
+                options.array = true;
+                Ok(())
+            } else if meta.path.is_ident("name") {
+                options.name = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else if meta.path.is_ident("record_info") {
+                options.record_info = Some(meta.value()?.parse::<Path>()?);
+                Ok(())
+            } else {
+                Err(meta.error(
+                    "unsupported variant attribute, expected `array`, `name` or `record_info`",
+                ))
+            }
+        })?;
+    }
+    Ok(options)
+}
+
+fn field_rename(attrs: &[Attribute]) -> Result<Option<String>> {
+    let mut rename = None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("variant")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("unsupported variant attribute, expected `rename`"))
+            }
+        })?;
+    }
+    Ok(rename)
+}
+
+/// Fails if a field that isn't converted to a record field is renamed
+fn no_rename<'a>(fields: impl Iterator<Item = &'a Field>) -> Result<()> {
+    for f in fields {
+        if field_rename(&f.attrs)?.is_some() {
+            let attr = f.attrs.iter().find(|a| a.path().is_ident("variant"));
+            return Err(Error::new_spanned(
+                attr,
+                "`rename` only applies to fields of structs converted to records",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Adds a bound on the given trait to each type parameter
+fn add_bounds(generics: &mut Generics, bound: TokenStream2) {
+    let params: Vec<_> = generics.type_params().map(|p| p.ident.clone()).collect();
+    let where_clause = generics.make_where_clause();
+    for param in params {
+        where_clause
+            .predicates
+            .push(parse_quote! { #param: #bound });
+    }
+}
+
+fn shape(input: &DeriveInput) -> Result<Shape> {
+    let options = container_options(&input.attrs)?;
+    if options.array && (options.name.is_some() || options.record_info.is_some()) {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "`name` and `record_info` only apply to structs converted to records",
+        ));
+    }
+
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) if !options.array => {
+                let fields = fields
+                    .named
+                    .iter()
+                    .map(|f| {
+                        let ident = f.ident.clone().unwrap();
+                        let name = field_rename(&f.attrs)?.unwrap_or_else(|| ident.to_string());
+                        Ok((Member::Named(ident), name))
+                    })
+                    .collect::<Result<_>>()?;
+                let name = options.name.unwrap_or_else(|| input.ident.to_string());
+                Ok(Shape::Record(name, options.record_info, fields))
+            }
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 && !options.array => {
+                no_rename(fields.unnamed.iter())?;
+                Ok(Shape::Newtype)
+            }
+            Fields::Unit if !options.array => Ok(Shape::Unit),
+            fields => {
+                no_rename(fields.iter())?;
+                Ok(Shape::Array(fields.members().collect()))
+            }
+        },
+        Data::Enum(data)
+            if !options.array && options.name.is_none() && options.record_info.is_none() =>
+        {
+            data.variants
+                .iter()
+                .map(|v| match v.fields {
+                    Fields::Unit => Ok(v.ident.clone()),
+                    _ => Err(Error::new_spanned(
+                        v,
+                        "only enums without fields can be converted to variants",
+                    )),
+                })
+                .collect::<Result<_>>()
+                .map(Shape::Enum)
+        }
+        Data::Enum(_) => Err(Error::new_spanned(
+            &input.ident,
+            "`array`, `name` and `record_info` only apply to structs",
+        )),
+        Data::Union(_) => Err(Error::new_spanned(
+            &input.ident,
+            "unions can't be converted to variants",
+        )),
+    }
+}
+
+fn to_variant_body(shape: &Shape) -> TokenStream2 {
+    match shape {
+        Shape::Record(name, record_info, fields) => {
+            let (members, names): (Vec<_>, Vec<_>) = fields.iter().cloned().unzip();
+            let info = match record_info {
+                Some(path) => quote! { #path() },
+                None => quote! { ::core::option::Option::None },
+            };
+            quote! {
+                ::variant_rs::Variant::Record(::variant_rs::com_types::record::Record::new(
+                    ::variant_rs::com_types::record::RecordType {
+                        name: ::std::string::String::from(#name),
+                        guid: ::core::default::Default::default(),
+                        info: #info,
+                    },
+                    ::std::vec![
+                        #( (
+                            ::std::string::String::from(#names),
+                            ::variant_rs::ToVariant::to_variant(self.#members),
+                        ), )*
+                    ],
+                ))
+            }
+        }
+        Shape::Array(members) => quote! {
+            ::variant_rs::Variant::Array(::variant_rs::com_types::safearray::SafeArray::from_vec(
+                ::std::vec![ #( ::variant_rs::ToVariant::to_variant(self.#members), )* ],
+            ))
+        },
+        Shape::Newtype => quote! { ::variant_rs::ToVariant::to_variant(self.0) },
+        Shape::Unit => quote! { ::variant_rs::Variant::Empty },
+        Shape::Enum(_) => quote! { ::variant_rs::Variant::I32(self as i32) },
+    }
+}
+
+fn from_variant_body(shape: &Shape) -> TokenStream2 {
+    let error = quote! { ::variant_rs::convert::VariantConversionError };
+    let from_variant = quote! { ::variant_rs::FromVariant::from_variant };
+    match shape {
+        Shape::Record(_, _, fields) => {
+            let (members, names): (Vec<_>, Vec<_>) = fields.iter().cloned().unzip();
+            quote! {
+                let mut record =
+                    <::variant_rs::com_types::record::Record as ::variant_rs::FromVariant>::from_variant(
+                        variant,
+                    )?;
+                ::core::result::Result::Ok(Self {
+                    #( #members: #from_variant(record.take(#names).ok_or_else(|| {
+                        #error::MissingRecordField(::std::string::String::from(#names))
+                    })?)?, )*
+                })
+            }
+        }
+        Shape::Array(members) => {
+            let count = members.len();
+            quote! {
+                let elements =
+                    <::std::vec::Vec<::variant_rs::Variant<'static>> as ::variant_rs::FromVariant>::from_variant(
+                        variant,
+                    )?;
+                if elements.len() != #count {
+                    return ::core::result::Result::Err(#error::ArrayBoundsMismatch);
+                }
+                let mut elements = elements.into_iter();
+                ::core::result::Result::Ok(Self {
+                    #( #members: #from_variant(elements.next().unwrap())?, )*
+                })
+            }
+        }
+        Shape::Newtype => quote! {
+            ::core::result::Result::Ok(Self(#from_variant(variant)?))
+        },
+        Shape::Unit => quote! {
+            let _ = variant;
+            ::core::result::Result::Ok(Self)
+        },
+        Shape::Enum(variants) => quote! {
+            let value = <i32 as ::variant_rs::FromVariant>::from_variant(variant)?;
+            #(
+                if value == Self::#variants as i32 {
+                    return ::core::result::Result::Ok(Self::#variants);
+                }
+            )*
+            ::core::result::Result::Err(#error::InvalidEnumValue(value))
+        },
+    }
+}
+
+/// Derives `ToVariant`, see the [crate documentation](crate) for the conversion rules
+#[proc_macro_derive(ToVariant, attributes(variant))]
+pub fn derive_to_variant(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let shape = match shape(&input) {
+        Ok(shape) => shape,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let ident = &input.ident;
+    let mut generics = input.generics.clone();
+    add_bounds(&mut generics, quote! { ::variant_rs::ToVariant });
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let body = to_variant_body(&shape);
+    quote! {
+        impl #impl_generics ::variant_rs::ToVariant for #ident #ty_generics #where_clause {
+            fn to_variant(self) -> ::variant_rs::Variant<'static> {
+                #body
+            }
+        }
+    }
+    .into()
+}
+
+/// Derives `FromVariant`, see the [crate documentation](crate) for the conversion rules
+#[proc_macro_derive(FromVariant, attributes(variant))]
+pub fn derive_from_variant(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let shape = match shape(&input) {
+        Ok(shape) => shape,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let ident = &input.ident;
+    let mut generics = input.generics.clone();
+    add_bounds(&mut generics, quote! { ::variant_rs::FromVariant });
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let body = from_variant_body(&shape);
+    quote! {
+        impl #impl_generics ::variant_rs::FromVariant for #ident #ty_generics #where_clause {
+            fn from_variant(
+                variant: ::variant_rs::Variant<'_>,
+            ) -> ::core::result::Result<Self, ::variant_rs::convert::VariantConversionError> {
+                #body
+            }
+        }
+    }
+    .into()
+}